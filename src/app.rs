@@ -1,59 +1,326 @@
+use crate::check::{CheckResult, CheckState};
+use crate::form::ApiForm;
+use crate::history::History;
+use crate::metrics::Metrics;
+use crate::status_page::CheckStatus;
+use crate::storage::{Storage, StoredResult};
 use crate::utils;
+use crate::view::View;
 
-use std::convert::TryInto;
-
-use utils::yarn::ApisConfig;
+use chrono::Utc;
+use ratatui::widgets::TableState;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
+use utils::yarn::{Api, ApisConfig};
 
 pub struct App {
     pub configs: ApisConfig,
     pub apis_infos: Vec<ListRequests>,
+    pub history: HashMap<usize, History>,
+    pub storage: Option<Storage>,
+    pub metrics: Arc<Mutex<Metrics>>,
+    pub status_page: Arc<Mutex<Vec<CheckStatus>>>,
+    pub mode: Mode,
+    pub view: View,
+    /// Key of the selected check.
+    pub selected: Option<String>,
+    pub table_state: TableState,
+    /// Message shown above the table, e.g. when saving the config failed.
+    pub notice: Option<String>,
+}
+
+/// Rows moved by PgUp/PgDn.
+pub const PAGE: isize = 10;
+
+/// What the TUI keys currently act on.
+#[derive(Debug, Clone)]
+pub enum Mode {
+    Normal,
+    Form(ApiForm),
+    /// Waiting for `y` to delete the check with this key.
+    ConfirmDelete(String),
+    /// Details of the check with this key, scrolled down by `scroll` lines.
+    Detail {
+        key: String,
+        scroll: u16,
+    },
+    /// Typing the search query.
+    Search,
+    /// List of key bindings.
+    Help,
 }
 
-#[derive(Debug)]
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct ApiInformation {
     pub name: String,
     pub url: String,
     pub method: String,
-    pub status: String,
+    pub result: CheckResult,
 }
 
-#[derive(Debug)]
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct ListRequests {
     pub(crate) id: usize,
+    /// Key of the check under `requests` in the config file.
+    pub key: String,
     pub data: ApiInformation,
     pub interval: u64,
+    pub paused: bool,
+    /// System notifications of the check are not sent until then.
+    pub muted_until: Option<Instant>,
+}
+
+impl ListRequests {
+    pub fn is_muted(&self) -> bool {
+        self.muted_until.is_some_and(|until| until > Instant::now())
+    }
+}
+
+/// Log a new result. State changes are logged at `info`, or `warn` when the
+/// check went down.
+fn log_result(data: &ApiInformation, previous: Option<CheckState>) {
+    let result = &data.result;
+    let latency_ms = result.latency().map(|l| l.as_millis());
+    let error = result.error_message.as_deref().unwrap_or("");
+
+    if previous == Some(result.state) {
+        tracing::debug!(
+            check = data.name,
+            state = %result.state,
+            status = result.http_status,
+            latency_ms,
+            "check finished"
+        );
+    } else if result.is_failure() {
+        tracing::warn!(
+            check = data.name,
+            url = data.url,
+            from = %previous.unwrap_or(CheckState::Pending),
+            to = %result.state,
+            status = result.http_status,
+            latency_ms,
+            error,
+            "state changed"
+        );
+    } else {
+        tracing::info!(
+            check = data.name,
+            url = data.url,
+            from = %previous.unwrap_or(CheckState::Pending),
+            to = %result.state,
+            status = result.http_status,
+            latency_ms,
+            "state changed"
+        );
+    }
 }
 
 impl App {
-    pub fn new(configs: ApisConfig) -> App {
+    pub fn new(configs: ApisConfig, storage: Option<Storage>) -> App {
         App {
             configs,
             apis_infos: Vec::<ListRequests>::new(),
+            history: HashMap::new(),
+            storage,
+            metrics: Arc::new(Mutex::new(Metrics::default())),
+            status_page: Arc::new(Mutex::new(Vec::new())),
+            mode: Mode::Normal,
+            view: View::default(),
+            selected: None,
+            table_state: TableState::default(),
+            notice: None,
+        }
+    }
+
+    /// Replace the rows with the latest results and record the new ones in
+    /// the history. Returns the rows holding a result not seen before.
+    pub fn append_status(&mut self, result: Vec<ListRequests>) -> Vec<ListRequests> {
+        let mut new_results = Vec::new();
+
+        for request in &result {
+            let history = self
+                .history
+                .entry(request.id)
+                .or_insert_with(|| History::new(self.configs.history_size));
+            let previous = history.last().map(|r| r.state);
+            let added = history.push(&request.data.result);
+
+            if added {
+                log_result(&request.data, previous);
+                self.metrics
+                    .lock()
+                    .unwrap()
+                    .record(&request.key, &request.data);
+                new_results.push(request.clone());
+            }
+
+            if let (true, Some(storage)) = (added, self.storage.as_mut()) {
+                let record = StoredResult::new(&request.key, &request.data);
+                if let Err(e) = storage.save(&record) {
+                    tracing::warn!("failed to store result of {}: {e}", request.key);
+                }
+            }
+        }
+        self.apis_infos = result;
+
+        if !new_results.is_empty() {
+            self.publish_status();
+        }
+        new_results
+    }
+
+    /// Refresh what the status page shows from the rows and their history.
+    fn publish_status(&self) {
+        let mut checks: Vec<CheckStatus> = self
+            .apis_infos
+            .iter()
+            .map(|row| {
+                let group = self
+                    .configs
+                    .requests
+                    .get(&row.key)
+                    .and_then(|api| api.group.as_deref());
+                CheckStatus::new(row, group, self.history.get(&row.id))
+            })
+            .collect();
+        checks.sort_by(|a, b| a.name.cmp(&b.name));
+
+        *self.status_page.lock().unwrap() = checks;
+    }
+
+    /// Fill the history of a check with the results kept in storage.
+    fn load_history(&mut self, id: usize, key: &str) {
+        let mut history = History::new(self.configs.history_size);
+
+        if let (Some(storage), Some(config)) = (&self.storage, &self.configs.storage) {
+            let since = Utc::now() - chrono::Duration::hours(config.load_hours as i64);
+
+            match storage.load(key, since, self.configs.history_size) {
+                Ok(results) => {
+                    for result in &results {
+                        history.push(result);
+                    }
+                }
+                Err(e) => tracing::warn!("failed to load history of {key}: {e}"),
+            }
         }
+
+        self.history.insert(id, history);
     }
 
-    pub fn append_status(&mut self, result: Vec<ListRequests>) {
-        self.apis_infos = result
+    fn new_row(&mut self, id: usize, key: &str, api: &Api) -> ListRequests {
+        self.load_history(id, key);
+
+        let result = match self.history.get(&id).and_then(|h| h.last()) {
+            Some(last) => last.clone(),
+            None => CheckResult::pending(),
+        };
+        ListRequests {
+            id,
+            key: key.to_string(),
+            data: ApiInformation {
+                name: api.name.to_string(),
+                url: api.request.url.to_string(),
+                method: api.request.method.to_string(),
+                result,
+            },
+            interval: api.interval,
+            paused: false,
+            muted_until: None,
+        }
     }
 
     pub fn format_api_infos(&mut self) {
-        for (id, config) in self.configs.requests.iter().enumerate() {
-            let api = ApiInformation {
-                name:config.1.name.to_string(),
-                url: config.1.request.url.to_string(),
-                method: config.1.request.method.to_string(),
-                status: "WAINTING".to_string(),
-            };
-            let new_request = ListRequests {
-                id: id.try_into().unwrap(),
-                data: api,
-                interval: config.1.interval
-            };
+        let configs = self.configs.requests.clone();
+
+        for (id, (key, api)) in configs.iter().enumerate() {
+            let new_request = self.new_row(id, key, api);
             self.apis_infos.push(new_request)
         }
+
+        self.publish_status();
+    }
+
+    /// Rows shown in the table, filtered and sorted by `view`.
+    pub fn visible_rows(&self) -> Vec<&ListRequests> {
+        self.view.apply(&self.apis_infos, &self.configs.requests)
+    }
+
+    /// The selected row, when it is shown.
+    pub fn selected_row(&self) -> Option<&ListRequests> {
+        let key = self.selected.as_ref()?;
+        self.visible_rows().into_iter().find(|row| row.key == *key)
+    }
+
+    /// Move the selection by `offset` shown rows. The selection follows the
+    /// check, not its position, so it stays put when rows are re-sorted.
+    pub fn move_selection(&mut self, offset: isize) {
+        let rows = self.visible_rows();
+        let Some(last) = rows.len().checked_sub(1) else {
+            self.selected = None;
+            return;
+        };
+        let current = self
+            .selected
+            .as_ref()
+            .and_then(|key| rows.iter().position(|row| row.key == *key));
+        let index = match current {
+            Some(index) => index.saturating_add_signed(offset).min(last),
+            None => 0,
+        };
+        self.selected = Some(rows[index].key.to_string());
     }
 
+    pub fn select_first(&mut self) {
+        self.selected = self.visible_rows().first().map(|row| row.key.to_string());
+    }
+
+    pub fn select_last(&mut self) {
+        self.selected = self.visible_rows().last().map(|row| row.key.to_string());
+    }
+
+    pub fn row(&self, key: &str) -> Option<&ListRequests> {
+        self.apis_infos.iter().find(|row| row.key == key)
+    }
+
+    /// Add a check, or update an existing one while keeping its history.
+    /// Returns the row to hand to the scheduler.
+    pub fn upsert_check(&mut self, key: &str, api: Api) -> ListRequests {
+        let row = match self.row(key) {
+            Some(existing) => {
+                let mut row = existing.clone();
+                row.data.name = api.name.to_string();
+                row.data.url = api.request.url.to_string();
+                row.data.method = api.request.method.to_string();
+                row.interval = api.interval;
+                row
+            }
+            None => {
+                let id = self.apis_infos.iter().map(|r| r.id + 1).max().unwrap_or(0);
+                self.new_row(id, key, &api)
+            }
+        };
+
+        match self.apis_infos.iter_mut().find(|r| r.id == row.id) {
+            Some(existing) => *existing = row.clone(),
+            None => self.apis_infos.push(row.clone()),
+        }
+        self.configs.requests.insert(key.to_string(), api);
+        self.publish_status();
+        row
+    }
+
+    /// Forget a check and its history. Returns its id.
+    pub fn remove_check(&mut self, key: &str) -> Option<usize> {
+        let id = self.row(key)?.id;
+
+        self.configs.requests.remove(key);
+        self.apis_infos.retain(|r| r.id != id);
+        self.history.remove(&id);
+        self.metrics.lock().unwrap().remove(key);
+        self.publish_status();
+        Some(id)
+    }
 }
@@ -0,0 +1,204 @@
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+
+use crate::utils::yarn::Assertion;
+
+/// Walk a dotted path (`data.items.0.id`) through a JSON value.
+/// Numeric segments index into arrays.
+pub(crate) fn json_field<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |current, field| match current {
+            Value::Array(items) => field.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => current.get(field),
+        })
+}
+
+fn check_assertion(assertion: &Assertion, headers: &HeaderMap, body: &str) -> Option<String> {
+    match assertion {
+        Assertion::JsonEquals { path, value } | Assertion::JsonNotEquals { path, value } => {
+            let json: Value = match serde_json::from_str(body) {
+                Ok(json) => json,
+                Err(e) => return Some(format!("{path}: body is not JSON ({e})")),
+            };
+            let found = json_field(&json, path);
+            let equals = found == Some(value);
+
+            match (assertion, found) {
+                (Assertion::JsonEquals { .. }, None) => Some(format!("{path}: not found")),
+                (Assertion::JsonEquals { .. }, Some(found)) if !equals => {
+                    Some(format!("{path}: expected {value}, got {found}"))
+                }
+                (Assertion::JsonNotEquals { .. }, _) if equals => {
+                    Some(format!("{path}: must not be {value}"))
+                }
+                _ => None,
+            }
+        }
+        Assertion::BodyMatches { pattern } => match Regex::new(pattern) {
+            Ok(re) if re.is_match(body) => None,
+            Ok(_) => Some(format!("body does not match /{pattern}/")),
+            Err(e) => Some(format!("invalid regex /{pattern}/: {e}")),
+        },
+        Assertion::BodyContains { text } => {
+            if body.contains(text.as_str()) {
+                None
+            } else {
+                Some(format!("body does not contain \"{text}\""))
+            }
+        }
+        Assertion::HeaderExists { name } => {
+            if headers.contains_key(name.as_str()) {
+                None
+            } else {
+                Some(format!("header {name} missing"))
+            }
+        }
+        Assertion::HeaderEquals { name, value } => match headers.get(name.as_str()) {
+            Some(found) if found.to_str().ok() == Some(value.as_str()) => None,
+            Some(found) => Some(format!(
+                "header {name}: expected \"{value}\", got \"{}\"",
+                found.to_str().unwrap_or("<binary>")
+            )),
+            None => Some(format!("header {name} missing")),
+        },
+        Assertion::BodySize { min, max } => {
+            let size = body.len();
+            match (min, max) {
+                (Some(min), _) if size < *min => {
+                    Some(format!("body size {size}B below minimum {min}B"))
+                }
+                (_, Some(max)) if size > *max => {
+                    Some(format!("body size {size}B above maximum {max}B"))
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Run every assertion and return one message per failure.
+pub fn check_assertions(assertions: &[Assertion], headers: &HeaderMap, body: &str) -> Vec<String> {
+    assertions
+        .iter()
+        .filter_map(|assertion| check_assertion(assertion, headers, body))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+
+    const BODY: &str = r#"{"data":{"items":[{"id":7}],"status":"ok"}}"#;
+
+    fn check(assertion: Assertion) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        check_assertions(&[assertion], &headers, BODY).pop()
+    }
+
+    fn json_equals(path: &str, value: Value) -> Assertion {
+        Assertion::JsonEquals {
+            path: path.to_string(),
+            value,
+        }
+    }
+
+    fn json_not_equals(path: &str, value: Value) -> Assertion {
+        Assertion::JsonNotEquals {
+            path: path.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn json_assertions_follow_paths() {
+        assert_eq!(check(json_equals("data.items.0.id", json!(7))), None);
+        assert_eq!(
+            check(json_equals("data.status", json!("down"))),
+            Some(String::from(r#"data.status: expected "down", got "ok""#))
+        );
+        assert_eq!(
+            check(json_equals("data.missing", json!(1))),
+            Some(String::from("data.missing: not found"))
+        );
+        assert_eq!(check(json_not_equals("data.status", json!("down"))), None);
+        assert_eq!(
+            check(json_not_equals("data.status", json!("ok"))),
+            Some(String::from(r#"data.status: must not be "ok""#))
+        );
+        // A missing field is not equal to anything.
+        assert_eq!(check(json_not_equals("data.missing", json!("ok"))), None);
+
+        let failures = check_assertions(&[json_equals("a", json!(1))], &HeaderMap::new(), "<html>");
+        assert!(failures[0].starts_with("a: body is not JSON"));
+    }
+
+    #[test]
+    fn body_assertions() {
+        let matches = |pattern: &str| {
+            check(Assertion::BodyMatches {
+                pattern: pattern.to_string(),
+            })
+        };
+        assert_eq!(matches(r#""id":\d+"#), None);
+        assert_eq!(
+            matches("^ok$"),
+            Some(String::from("body does not match /^ok$/"))
+        );
+        assert!(matches("(").unwrap().starts_with("invalid regex /(/"));
+
+        let contains = |text: &str| {
+            check(Assertion::BodyContains {
+                text: text.to_string(),
+            })
+        };
+        assert_eq!(contains("\"ok\""), None);
+        assert_eq!(
+            contains("error"),
+            Some(String::from("body does not contain \"error\""))
+        );
+
+        let size = |min, max| check(Assertion::BodySize { min, max });
+        assert_eq!(size(Some(1), Some(BODY.len())), None);
+        assert_eq!(
+            size(Some(100), None),
+            Some(format!("body size {}B below minimum 100B", BODY.len()))
+        );
+        assert_eq!(
+            size(None, Some(10)),
+            Some(format!("body size {}B above maximum 10B", BODY.len()))
+        );
+    }
+
+    #[test]
+    fn header_assertions() {
+        let exists = |name: &str| {
+            check(Assertion::HeaderExists {
+                name: name.to_string(),
+            })
+        };
+        assert_eq!(exists("Content-Type"), None);
+        assert_eq!(exists("etag"), Some(String::from("header etag missing")));
+
+        let equals = |name: &str, value: &str| {
+            check(Assertion::HeaderEquals {
+                name: name.to_string(),
+                value: value.to_string(),
+            })
+        };
+        assert_eq!(equals("content-type", "application/json"), None);
+        assert_eq!(
+            equals("content-type", "text/html"),
+            Some(String::from(
+                r#"header content-type: expected "text/html", got "application/json""#
+            ))
+        );
+        assert_eq!(
+            equals("etag", "x"),
+            Some(String::from("header etag missing"))
+        );
+    }
+}
@@ -0,0 +1,456 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::app::ApiInformation;
+use crate::assertion::check_assertions;
+use crate::auth::TokenError;
+use crate::connection::{Phase, PhaseError};
+use crate::depends_cache::DependsCache;
+use crate::interpolate::{expand_api, Secrets};
+use crate::request::{request_api, run_steps, ApiResponse, Timings};
+use crate::template::render_request;
+use crate::utils::notify::send_notify;
+use crate::utils::yarn::{Api, Depends, Request};
+
+/// Health of a check after its last run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckState {
+    /// Not run yet.
+    Pending,
+    Up,
+    /// Answered as expected, but slower than `warn_latency_ms`.
+    Degraded,
+    Down,
+    /// The request could not be built, so the endpoint was never reached.
+    Unknown,
+}
+
+impl fmt::Display for CheckState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CheckState::Pending => "PENDING",
+            CheckState::Up => "UP",
+            CheckState::Degraded => "DEGRADED",
+            CheckState::Down => "DOWN",
+            CheckState::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+impl FromStr for CheckState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PENDING" => Ok(CheckState::Pending),
+            "UP" => Ok(CheckState::Up),
+            "DEGRADED" => Ok(CheckState::Degraded),
+            "DOWN" => Ok(CheckState::Down),
+            "UNKNOWN" => Ok(CheckState::Unknown),
+            _ => Err(format!("unknown check state {s}")),
+        }
+    }
+}
+
+/// Why a check is not `Up`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Dns,
+    Connect,
+    Tls,
+    Timeout,
+    BodyRead,
+    /// The server answered with another status than `expected_status`.
+    Status,
+    Assertion,
+    Latency,
+    /// Invalid URL, method or header; nothing was sent.
+    Request,
+    /// A `depends_on` step or the OAuth2 token request failed; the check
+    /// itself was not sent.
+    Depends,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorKind::Dns => "dns",
+            ErrorKind::Connect => "connect",
+            ErrorKind::Tls => "tls",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::BodyRead => "body_read",
+            ErrorKind::Status => "status",
+            ErrorKind::Assertion => "assertion",
+            ErrorKind::Latency => "latency",
+            ErrorKind::Request => "request",
+            ErrorKind::Depends => "depends",
+        })
+    }
+}
+
+impl FromStr for ErrorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dns" => Ok(ErrorKind::Dns),
+            "connect" => Ok(ErrorKind::Connect),
+            "tls" => Ok(ErrorKind::Tls),
+            "timeout" => Ok(ErrorKind::Timeout),
+            "body_read" => Ok(ErrorKind::BodyRead),
+            "status" => Ok(ErrorKind::Status),
+            "assertion" => Ok(ErrorKind::Assertion),
+            "latency" => Ok(ErrorKind::Latency),
+            "request" => Ok(ErrorKind::Request),
+            "depends" => Ok(ErrorKind::Depends),
+            _ => Err(format!("unknown error kind {s}")),
+        }
+    }
+}
+
+/// Bytes of the response body kept for the detail view.
+const BODY_PREVIEW: usize = 4096;
+
+/// Response of the last run, shown in the TUI detail view. Not kept in
+/// the history or in storage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseSnapshot {
+    pub headers: Vec<(String, String)>,
+    /// Start of the body, at most `BODY_PREVIEW` bytes.
+    pub body: String,
+    pub body_size: usize,
+}
+
+impl ResponseSnapshot {
+    fn new(resp: &ApiResponse) -> ResponseSnapshot {
+        let mut end = resp.body.len().min(BODY_PREVIEW);
+        while !resp.body.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        ResponseSnapshot {
+            headers: resp
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+                    (name.to_string(), value)
+                })
+                .collect(),
+            body: resp.body[..end].to_string(),
+            body_size: resp.body.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub state: CheckState,
+    /// Status returned by the server, `None` when no response was received.
+    pub http_status: Option<u16>,
+    pub error_kind: Option<ErrorKind>,
+    pub error_message: Option<String>,
+    pub assertion_failures: Vec<String>,
+    pub timings: Option<Timings>,
+    pub attempts: u32,
+    pub checked_at: Option<DateTime<Utc>>,
+    pub response: Option<ResponseSnapshot>,
+}
+
+impl CheckResult {
+    pub fn pending() -> CheckResult {
+        CheckResult {
+            state: CheckState::Pending,
+            http_status: None,
+            error_kind: None,
+            error_message: None,
+            assertion_failures: Vec::new(),
+            timings: None,
+            attempts: 0,
+            checked_at: None,
+            response: None,
+        }
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        self.timings.as_ref().map(|t| t.total)
+    }
+
+    /// Whether the check should be reported as a failure.
+    pub fn is_failure(&self) -> bool {
+        matches!(self.state, CheckState::Down | CheckState::Unknown)
+    }
+}
+
+/// Map a transport error returned by `request_api` to an `ErrorKind`.
+pub fn classify_error(error: &(dyn Error + 'static)) -> ErrorKind {
+    let mut kind = ErrorKind::Request;
+    let mut source = Some(error);
+
+    while let Some(err) = source {
+        if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() {
+                return ErrorKind::Timeout;
+            }
+            if e.is_body() || e.is_decode() {
+                return ErrorKind::BodyRead;
+            }
+            if e.is_connect() || e.is_request() {
+                kind = ErrorKind::Connect;
+            }
+        }
+        if let Some(e) = err.downcast_ref::<PhaseError>() {
+            match e.phase {
+                Phase::Dns => return ErrorKind::Dns,
+                Phase::Tls => return ErrorKind::Tls,
+                Phase::Body => return ErrorKind::BodyRead,
+                Phase::Connect | Phase::Send => kind = ErrorKind::Connect,
+            }
+        }
+        if err.downcast_ref::<TokenError>().is_some() {
+            return ErrorKind::Depends;
+        }
+        if err.downcast_ref::<native_tls::Error>().is_some() {
+            return ErrorKind::Tls;
+        }
+        if let Some(e) = err.downcast_ref::<io::Error>() {
+            if e.kind() == io::ErrorKind::TimedOut {
+                return ErrorKind::Timeout;
+            }
+        }
+        if err.to_string().starts_with("dns error") {
+            return ErrorKind::Dns;
+        }
+        source = err.source();
+    }
+
+    kind
+}
+
+/// Full error chain on one line, e.g. `error sending request: connection refused`.
+pub fn error_chain(error: &(dyn Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+
+    while let Some(err) = source {
+        let text = err.to_string();
+        if !message.contains(&text) {
+            message = format!("{message}: {text}");
+        }
+        source = err.source();
+    }
+
+    message
+}
+
+/// A result for a check whose request could not be sent.
+fn not_sent(state: CheckState, kind: ErrorKind, message: String) -> CheckResult {
+    CheckResult {
+        state,
+        error_kind: Some(kind),
+        error_message: Some(message),
+        attempts: 1,
+        checked_at: Some(Utc::now()),
+        ..CheckResult::pending()
+    }
+}
+
+/// Whether `result` may come from a revoked cached token.
+fn unauthorized(api: &Api, result: &CheckResult) -> bool {
+    result.http_status == Some(401) && api.expected_status != 401
+}
+
+/// Send the request of a check, retrying as configured. A `401` is not
+/// retried when `cached` steps were used, they are refreshed instead.
+async fn send_with_retries(
+    api: &Api,
+    request: &Request,
+    timeout: Duration,
+    cache: &DependsCache,
+    cached: bool,
+    secrets: &mut Secrets,
+) -> CheckResult {
+    let mut attempts = 0;
+
+    let mut result = loop {
+        attempts += 1;
+
+        let response = request_api(request, timeout, cache, secrets).await;
+        let result = evaluate_response(api, response);
+
+        let stale = cached && unauthorized(api, &result);
+        if result.state != CheckState::Down || attempts > api.retries || stale {
+            break result;
+        }
+
+        tokio::time::sleep(api.retry_backoff.delay(attempts)).await;
+    };
+
+    result.attempts = attempts;
+    result
+}
+
+async fn run_check(api: &Api, cache: &DependsCache, secrets: &mut Secrets) -> CheckResult {
+    let timeout = Duration::from_secs(api.timeout);
+    let mut refresh = false;
+
+    loop {
+        let (variables, cached) = match &api.depends_on {
+            Some(depends_on) => {
+                match run_steps(depends_on.steps(), timeout, cache, refresh, secrets).await {
+                    Ok(steps) => steps,
+                    Err(e) => return not_sent(CheckState::Down, ErrorKind::Depends, e),
+                }
+            }
+            None => (Value::Null, false),
+        };
+
+        let request = match render_request(&api.request, &variables) {
+            Ok(request) => request,
+            Err(e) => {
+                let message = format!("template error in request.{e}");
+                return not_sent(CheckState::Unknown, ErrorKind::Request, message);
+            }
+        };
+
+        let result = send_with_retries(api, &request, timeout, cache, cached, secrets).await;
+
+        // A cached token may have been revoked: get a new one and try again.
+        if cached && !refresh && unauthorized(api, &result) {
+            refresh = true;
+            continue;
+        }
+        return result;
+    }
+}
+
+/// Hide the values resolved from `${...}` references in what is shown of
+/// a result.
+fn mask_result(result: &mut CheckResult, secrets: &Secrets) {
+    if let Some(message) = &mut result.error_message {
+        *message = secrets.mask(message);
+    }
+    for failure in &mut result.assertion_failures {
+        *failure = secrets.mask(failure);
+    }
+    if let Some(response) = &mut result.response {
+        for (_, value) in &mut response.headers {
+            *value = secrets.mask(value);
+        }
+        response.body = secrets.mask(&response.body);
+    }
+}
+
+/// Run a check once. Shared `depends_on` steps are looked up in `steps`,
+/// and their responses reused from `cache` while they are valid.
+pub async fn verify_api(
+    api: &Api,
+    steps: &HashMap<String, Depends>,
+    cache: &DependsCache,
+) -> ApiInformation {
+    let mut secrets = Secrets::default();
+
+    let resolved = match &api.depends_on {
+        Some(depends_on) => depends_on.resolve(steps).map(|depends_on| Api {
+            depends_on: Some(depends_on),
+            ..api.clone()
+        }),
+        None => Ok(api.clone()),
+    };
+
+    let mut result = match resolved.and_then(|api| expand_api(&api, &mut secrets)) {
+        Ok(expanded) => run_check(&expanded, cache, &mut secrets).await,
+        Err(e) => not_sent(CheckState::Unknown, ErrorKind::Request, e),
+    };
+    mask_result(&mut result, &secrets);
+
+    if result.is_failure() && api.system_notify {
+        let notify_message = format!(
+            "{} (after {} attempts)",
+            result.error_message.as_deref().unwrap_or("Request failed"),
+            result.attempts
+        );
+        if let Err(e) = send_notify(api.name.as_str(), "dialog-error", notify_message.as_str()) {
+            tracing::warn!("failed to send notification for {}: {e}", api.name);
+        }
+    }
+
+    ApiInformation {
+        name: api.name.to_string(),
+        url: api.request.url.to_string(),
+        method: api.request.method.to_string(),
+        result,
+    }
+}
+
+/// Turn the outcome of a single attempt into a `CheckResult`.
+fn evaluate_response(
+    api: &Api,
+    response: Result<ApiResponse, Box<dyn Error + Send + Sync>>,
+) -> CheckResult {
+    let mut result = CheckResult {
+        checked_at: Some(Utc::now()),
+        attempts: 1,
+        ..CheckResult::pending()
+    };
+
+    let resp = match response {
+        Ok(resp) => resp,
+        Err(e) => {
+            let kind = classify_error(e.as_ref());
+            result.state = if kind == ErrorKind::Request {
+                CheckState::Unknown
+            } else {
+                CheckState::Down
+            };
+            result.error_kind = Some(kind);
+            result.error_message = Some(error_chain(e.as_ref()));
+            return result;
+        }
+    };
+
+    let status = resp.status.as_u16();
+    let latency_ms = resp.timings.total.as_millis() as u64;
+    result.http_status = Some(status);
+    result.response = Some(ResponseSnapshot::new(&resp));
+    result.timings = Some(resp.timings);
+
+    if status != api.expected_status {
+        result.state = CheckState::Down;
+        result.error_kind = Some(ErrorKind::Status);
+        result.error_message = Some(format!(
+            "Request failed with status {status}, expected {}",
+            api.expected_status
+        ));
+        return result;
+    }
+
+    result.assertion_failures = check_assertions(&api.assertions, &resp.headers, &resp.body);
+
+    if !result.assertion_failures.is_empty() {
+        result.state = CheckState::Down;
+        result.error_kind = Some(ErrorKind::Assertion);
+        result.error_message = Some(format!(
+            "Assertions failed: {}",
+            result.assertion_failures.join("; ")
+        ));
+    } else if let Some(max) = api.max_latency_ms.filter(|max| latency_ms > *max) {
+        result.state = CheckState::Down;
+        result.error_kind = Some(ErrorKind::Latency);
+        result.error_message = Some(format!("Request took {latency_ms}ms, max is {max}ms"));
+    } else if let Some(warn) = api.warn_latency_ms.filter(|warn| latency_ms > *warn) {
+        result.state = CheckState::Degraded;
+        result.error_kind = Some(ErrorKind::Latency);
+        result.error_message = Some(format!("Request took {latency_ms}ms, warn above {warn}ms"));
+    } else {
+        result.state = CheckState::Up;
+    }
+
+    result
+}
@@ -0,0 +1,207 @@
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::client::conn::http1;
+use hyper_util::client::proxy::matcher::Matcher;
+use hyper_util::rt::TokioIo;
+use reqwest::header::{HeaderMap, HeaderValue, HOST};
+use reqwest::{Method, StatusCode, Url};
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{lookup_host, TcpStream};
+use tokio_native_tls::TlsConnector;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Step of an exchange over a [`send`] connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Dns,
+    Connect,
+    Tls,
+    Send,
+    Body,
+}
+
+/// An exchange failed during `phase`.
+#[derive(Debug)]
+pub struct PhaseError {
+    pub phase: Phase,
+    source: BoxError,
+}
+
+impl PhaseError {
+    fn new(phase: Phase, source: impl Into<BoxError>) -> PhaseError {
+        PhaseError {
+            phase,
+            source: source.into(),
+        }
+    }
+}
+
+impl fmt::Display for PhaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self.phase {
+            Phase::Dns => "dns error",
+            Phase::Connect => "tcp connect error",
+            Phase::Tls => "tls handshake error",
+            Phase::Send => "error sending request",
+            Phase::Body => "error reading response body",
+        };
+        write!(f, "{text}")
+    }
+}
+
+impl Error for PhaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Durations of the phases of one exchange. `dns` is `None` for an IP
+/// address and `tls` for plain HTTP.
+#[derive(Debug, Clone, Default)]
+pub struct Phases {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+    /// From the request being written to the response headers.
+    pub ttfb: Duration,
+}
+
+pub struct Exchange {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub phases: Phases,
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// Whether reqwest would send a request to `url` through a proxy from the
+/// environment, where the phases of the target connection cannot be timed.
+pub fn proxied(url: &Url) -> bool {
+    url.as_str()
+        .parse()
+        .is_ok_and(|uri| Matcher::from_system().intercept(&uri).is_some())
+}
+
+async fn resolve(url: &Url, port: u16) -> Result<(Vec<SocketAddr>, Option<Duration>), BoxError> {
+    match url.host() {
+        Some(url::Host::Domain(domain)) => {
+            let started = Instant::now();
+            let addrs: Vec<SocketAddr> = lookup_host((domain, port)).await?.collect();
+            if addrs.is_empty() {
+                return Err(format!("no address found for {domain}").into());
+            }
+            Ok((addrs, Some(started.elapsed())))
+        }
+        Some(url::Host::Ipv4(ip)) => Ok((vec![SocketAddr::new(ip.into(), port)], None)),
+        Some(url::Host::Ipv6(ip)) => Ok((vec![SocketAddr::new(ip.into(), port)], None)),
+        None => Err(String::from("url has no host").into()),
+    }
+}
+
+/// Open a TCP connection to the first address accepting it.
+async fn connect(addrs: &[SocketAddr]) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap())
+}
+
+/// Send one request over a new HTTP/1.1 connection opened for it, timing
+/// the DNS lookup, TCP connection and TLS handshake.
+pub async fn send(
+    method: Method,
+    url: &Url,
+    mut headers: HeaderMap,
+    body: Option<Vec<u8>>,
+) -> Result<Exchange, PhaseError> {
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| PhaseError::new(Phase::Connect, "url has no port"))?;
+    let (addrs, dns) = resolve(url, port)
+        .await
+        .map_err(|e| PhaseError::new(Phase::Dns, e))?;
+
+    let started = Instant::now();
+    let stream = connect(&addrs)
+        .await
+        .map_err(|e| PhaseError::new(Phase::Connect, e))?;
+    let connect = started.elapsed();
+    let _ = stream.set_nodelay(true);
+
+    let (stream, tls): (Box<dyn Io>, _) = if url.scheme() == "https" {
+        let started = Instant::now();
+        let connector =
+            native_tls::TlsConnector::new().map_err(|e| PhaseError::new(Phase::Tls, e))?;
+        let domain = url.host_str().unwrap_or_default();
+        let stream = TlsConnector::from(connector)
+            .connect(domain.trim_start_matches('[').trim_end_matches(']'), stream)
+            .await
+            .map_err(|e| PhaseError::new(Phase::Tls, e))?;
+        (Box::new(stream), Some(started.elapsed()))
+    } else {
+        (Box::new(stream), None)
+    };
+
+    let (mut sender, connection) = http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| PhaseError::new(Phase::Send, e))?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    let host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let host = HeaderValue::from_str(&host).map_err(|e| PhaseError::new(Phase::Send, e))?;
+    headers.entry(HOST).or_insert(host);
+
+    let target = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    };
+    let mut request = hyper::Request::builder()
+        .method(method)
+        .uri(target)
+        .body(Full::new(Bytes::from(body.unwrap_or_default())))
+        .map_err(|e| PhaseError::new(Phase::Send, e))?;
+    *request.headers_mut() = headers;
+
+    let started = Instant::now();
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| PhaseError::new(Phase::Send, e))?;
+    let ttfb = started.elapsed();
+
+    let (parts, body) = response.into_parts();
+    let body = body
+        .collect()
+        .await
+        .map_err(|e| PhaseError::new(Phase::Body, e))?
+        .to_bytes();
+
+    Ok(Exchange {
+        status: parts.status,
+        headers: parts.headers,
+        body,
+        phases: Phases {
+            dns,
+            connect: Some(connect),
+            tls,
+            ttfb,
+        },
+    })
+}
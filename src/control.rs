@@ -0,0 +1,254 @@
+use axum::extract::{Path, Request, State};
+use axum::http::{header::AUTHORIZATION, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::app::{App, ListRequests};
+use crate::output::CheckReport;
+use crate::scheduler::Scheduler;
+use crate::utils::yarn::{Api, ControlConfig};
+use crate::validate;
+
+/// Change requested through the control API, applied by the main loop.
+#[derive(Debug)]
+pub enum ControlCommand {
+    List,
+    Get(String),
+    Put(String, Box<Api>),
+    Delete(String),
+    Pause(String),
+    Resume(String),
+    Run(String),
+}
+
+pub type ControlReply = Result<Value, (StatusCode, String)>;
+pub type ControlSender = mpsc::UnboundedSender<(ControlCommand, oneshot::Sender<ControlReply>)>;
+pub type ControlReceiver = mpsc::UnboundedReceiver<(ControlCommand, oneshot::Sender<ControlReply>)>;
+
+#[derive(Serialize, Debug)]
+struct ControlCheck {
+    #[serde(flatten)]
+    report: CheckReport,
+    paused: bool,
+    interval: u64,
+    next_run_in: u64,
+}
+
+fn describe(app: &App, row: &ListRequests) -> Value {
+    let interval = app
+        .configs
+        .requests
+        .get(&row.key)
+        .map(|api| api.interval)
+        .unwrap_or(row.interval);
+
+    serde_json::to_value(ControlCheck {
+        report: CheckReport::new(&row.key, &row.data),
+        paused: row.paused,
+        interval,
+        next_run_in: row.interval,
+    })
+    .unwrap()
+}
+
+fn not_found(key: &str) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("no check named {key}"))
+}
+
+fn set_paused(app: &mut App, scheduler: &mut Scheduler, key: &str, paused: bool) -> ControlReply {
+    let row = app
+        .apis_infos
+        .iter_mut()
+        .find(|row| row.key == key)
+        .ok_or_else(|| not_found(key))?;
+    row.paused = paused;
+    scheduler.set_paused(row.id, paused);
+    Ok(describe(app, app.row(key).unwrap()))
+}
+
+/// Create or replace the check `key`. Its name is not checked against the
+/// other checks: a reload applies a whole file, already validated, one
+/// check at a time.
+pub fn put(app: &mut App, scheduler: &mut Scheduler, key: String, api: Api) -> ControlReply {
+    let problems = validate::check_api(&key, &api, &app.configs.steps);
+    if !problems.is_empty() {
+        let problems: Vec<String> = problems
+            .into_iter()
+            .map(|(path, message)| format!("{path}: {message}"))
+            .collect();
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, problems.join("; ")));
+    }
+    let row = app.upsert_check(&key, api);
+    scheduler.upsert(row);
+    tracing::info!("check {key} saved");
+    Ok(describe(app, app.row(&key).unwrap()))
+}
+
+/// Apply one command to the running checks.
+pub fn apply(app: &mut App, scheduler: &mut Scheduler, command: ControlCommand) -> ControlReply {
+    match command {
+        ControlCommand::List => {
+            let mut rows: Vec<&ListRequests> = app.apis_infos.iter().collect();
+            rows.sort_by(|a, b| a.key.cmp(&b.key));
+            Ok(Value::Array(
+                rows.into_iter().map(|row| describe(app, row)).collect(),
+            ))
+        }
+        ControlCommand::Get(key) => {
+            let row = app.row(&key).ok_or_else(|| not_found(&key))?;
+            Ok(describe(app, row))
+        }
+        ControlCommand::Put(key, api) => {
+            if let Some(message) = validate::duplicate_name(Some(&key), &api, &app.configs.requests)
+            {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("requests.{key}.name: {message}"),
+                ));
+            }
+            put(app, scheduler, key, *api)
+        }
+        ControlCommand::Delete(key) => {
+            let id = app.remove_check(&key).ok_or_else(|| not_found(&key))?;
+            scheduler.remove(id);
+            tracing::info!("check {key} deleted");
+            Ok(Value::Null)
+        }
+        ControlCommand::Pause(key) => set_paused(app, scheduler, &key, true),
+        ControlCommand::Resume(key) => set_paused(app, scheduler, &key, false),
+        ControlCommand::Run(key) => {
+            let row = app.row(&key).ok_or_else(|| not_found(&key))?;
+            let api = app
+                .configs
+                .requests
+                .get(&key)
+                .ok_or_else(|| not_found(&key))?;
+            scheduler.run_now(row.id, api, &app.configs.steps);
+            Ok(describe(app, row))
+        }
+    }
+}
+
+/// Apply every command received since the last call.
+pub fn drain(app: &mut App, scheduler: &mut Scheduler, receiver: &mut ControlReceiver) {
+    while let Ok((command, reply)) = receiver.try_recv() {
+        let _ = reply.send(apply(app, scheduler, command));
+    }
+}
+
+async fn send(sender: &ControlSender, command: ControlCommand) -> Response {
+    let (reply, response) = oneshot::channel();
+    if sender.send((command, reply)).is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "shutting down").into_response();
+    }
+
+    match response.await {
+        Ok(Ok(Value::Null)) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Ok(value)) => Json(value).into_response(),
+        Ok(Err((status, message))) => {
+            (status, Json(serde_json::json!({ "error": message }))).into_response()
+        }
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "shutting down").into_response(),
+    }
+}
+
+/// Create or replace a check sent over HTTP. `${...}` references are
+/// refused: anyone reaching the API could otherwise read the environment
+/// and files of the daemon through a check sending them out.
+async fn put_check(sender: &ControlSender, key: String, api: Api) -> Response {
+    if serde_json::to_string(&api).unwrap().contains("${") {
+        let message = "${...} references are only expanded in the config file";
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({ "error": message })),
+        )
+            .into_response();
+    }
+    send(sender, ControlCommand::Put(key, Box::new(api))).await
+}
+
+async fn require_token(State(token): State<String>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|value| value == token);
+
+    if authorized {
+        next.run(request).await
+    } else {
+        StatusCode::UNAUTHORIZED.into_response()
+    }
+}
+
+/// Router exposing the checks under `/checks`. Commands are queued on
+/// `sender` and answered once the main loop has applied them.
+pub fn router(config: &ControlConfig, sender: ControlSender) -> Router {
+    let router =
+        Router::new()
+            .route(
+                "/checks",
+                get(|State(s): State<ControlSender>| async move {
+                    send(&s, ControlCommand::List).await
+                }),
+            )
+            .route(
+                "/checks/:key",
+                get(
+                    |State(s): State<ControlSender>, Path(key): Path<String>| async move {
+                        send(&s, ControlCommand::Get(key)).await
+                    },
+                )
+                .put(
+                    |State(s): State<ControlSender>,
+                     Path(key): Path<String>,
+                     Json(api): Json<Api>| async move {
+                        put_check(&s, key, api).await
+                    },
+                )
+                .delete(
+                    |State(s): State<ControlSender>, Path(key): Path<String>| async move {
+                        send(&s, ControlCommand::Delete(key)).await
+                    },
+                ),
+            )
+            .route(
+                "/checks/:key/pause",
+                post(
+                    |State(s): State<ControlSender>, Path(key): Path<String>| async move {
+                        send(&s, ControlCommand::Pause(key)).await
+                    },
+                ),
+            )
+            .route(
+                "/checks/:key/resume",
+                post(
+                    |State(s): State<ControlSender>, Path(key): Path<String>| async move {
+                        send(&s, ControlCommand::Resume(key)).await
+                    },
+                ),
+            )
+            .route(
+                "/checks/:key/run",
+                post(
+                    |State(s): State<ControlSender>, Path(key): Path<String>| async move {
+                        send(&s, ControlCommand::Run(key)).await
+                    },
+                ),
+            )
+            .with_state(sender);
+
+    match &config.token {
+        Some(token) => router.layer(middleware::from_fn_with_state(
+            token.to_string(),
+            require_token,
+        )),
+        None => router,
+    }
+}
@@ -0,0 +1,160 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::utils::yarn::{Depends, Request};
+
+/// Derived lifetimes end this much early, so a token is not sent right as
+/// it expires.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+
+struct Cached {
+    response: Value,
+    expires: Instant,
+}
+
+/// Responses of `depends_on` steps and OAuth2 tokens, reused until they
+/// expire so checks sharing a login do not request a new token on every
+/// run. Steps are keyed by the request as sent, templates rendered.
+#[derive(Clone, Default)]
+pub struct DependsCache {
+    entries: Arc<Mutex<HashMap<String, Cached>>>,
+}
+
+/// Same key for the same request, whatever the order of its maps.
+fn cache_key(request: &Request) -> String {
+    // serde_json objects are sorted by key.
+    serde_json::to_value(request)
+        .map(|value| value.to_string())
+        .unwrap_or_default()
+}
+
+impl DependsCache {
+    pub fn get(&self, request: &Request) -> Option<Value> {
+        self.lookup(&cache_key(request))
+    }
+
+    /// Keep `response` for as long as `depends` allows, if at all.
+    pub fn insert(&self, depends: &Depends, request: &Request, response: &Value) {
+        if let Some(ttl) = ttl(depends.cache_ttl, response) {
+            self.store(cache_key(request), response, ttl);
+        }
+    }
+
+    pub fn lookup(&self, key: &str) -> Option<Value> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|cached| cached.expires > Instant::now())
+            .map(|cached| cached.response.clone())
+    }
+
+    pub fn store(&self, key: String, response: &Value, ttl: Duration) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, cached| cached.expires > now);
+        entries.insert(
+            key,
+            Cached {
+                response: response.clone(),
+                expires: now + ttl,
+            },
+        );
+    }
+}
+
+/// How long a response can be reused: `cache_ttl` when set, otherwise the
+/// `expires_in` field or the earliest JWT `exp` claim of the response.
+/// `None` when it cannot be cached.
+pub fn ttl(cache_ttl: Option<u64>, response: &Value) -> Option<Duration> {
+    if let Some(seconds) = cache_ttl {
+        return (seconds > 0).then(|| Duration::from_secs(seconds));
+    }
+
+    let seconds = expires_in(response).or_else(|| jwt_expiry(response))?;
+    Duration::from_secs(seconds)
+        .checked_sub(EXPIRY_MARGIN)
+        .filter(|ttl| !ttl.is_zero())
+}
+
+fn expires_in(response: &Value) -> Option<u64> {
+    match response.get("expires_in")? {
+        Value::Number(seconds) => seconds.as_u64(),
+        Value::String(seconds) => seconds.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Seconds until the earliest `exp` of the JWTs found in `response`.
+fn jwt_expiry(response: &Value) -> Option<u64> {
+    let now = Utc::now().timestamp();
+    let mut tokens = Vec::new();
+    collect_strings(response, &mut tokens);
+
+    tokens
+        .into_iter()
+        .filter_map(jwt_exp)
+        .min()
+        .map(|exp| exp.saturating_sub(now).max(0) as u64)
+}
+
+fn collect_strings<'a>(value: &'a Value, strings: &mut Vec<&'a str>) {
+    match value {
+        Value::String(text) => strings.push(text),
+        Value::Array(items) => items.iter().for_each(|v| collect_strings(v, strings)),
+        Value::Object(fields) => fields.values().for_each(|v| collect_strings(v, strings)),
+        _ => {}
+    }
+}
+
+/// The `exp` claim of `token`, if it is a JWT.
+fn jwt_exp(token: &str) -> Option<i64> {
+    let mut parts = token.split('.');
+    let (_, payload, _) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let claims: Value = serde_json::from_slice(&payload).ok()?;
+    claims.get("exp")?.as_i64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn jwt(claims: &Value) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        format!("eyJhbGciOiJIUzI1NiJ9.{payload}.c2lnbmF0dXJl")
+    }
+
+    #[test]
+    fn jwt_exp_reads_the_claim() {
+        assert_eq!(
+            jwt_exp(&jwt(&json!({ "exp": 1700000000 }))),
+            Some(1700000000)
+        );
+        assert_eq!(jwt_exp(&jwt(&json!({ "sub": "me" }))), None);
+        assert_eq!(jwt_exp("not a token"), None);
+        assert_eq!(jwt_exp("a.%%%.c"), None);
+    }
+
+    #[test]
+    fn ttl_prefers_cache_ttl_then_expires_in_then_jwt() {
+        let exp = Utc::now().timestamp() + 3600;
+        let response = json!({ "expires_in": 60, "token": jwt(&json!({ "exp": exp })) });
+
+        assert_eq!(ttl(Some(5), &response), Some(Duration::from_secs(5)));
+        assert_eq!(ttl(Some(0), &response), None);
+        assert_eq!(ttl(None, &response), Some(Duration::from_secs(50)));
+
+        let ttl = ttl(None, &json!({ "token": jwt(&json!({ "exp": exp })) })).unwrap();
+        assert!(ttl > Duration::from_secs(3580) && ttl <= Duration::from_secs(3590));
+    }
+}
@@ -0,0 +1,273 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use std::collections::HashMap;
+
+use crate::utils::yarn::{
+    default_timeout, Api, ConfigMethod, Depends, ReqHash, Request, RetryBackoff,
+};
+use crate::validate;
+
+pub const FIELDS: [&str; 7] = [
+    "Name",
+    "URL",
+    "Method",
+    "Headers",
+    "Body",
+    "Expected status",
+    "Interval (s)",
+];
+
+const NAME: usize = 0;
+const URL: usize = 1;
+const METHOD: usize = 2;
+const HEADERS: usize = 3;
+const BODY: usize = 4;
+const EXPECTED_STATUS: usize = 5;
+const INTERVAL: usize = 6;
+
+pub enum FormAction {
+    None,
+    Cancel,
+    Submit,
+}
+
+/// Form to create a check, or edit the request of an existing one. Headers
+/// and body are written as `key: value` pairs separated by `;`, with `\;`
+/// and `\\` for a `;` or `\` in a key or value.
+#[derive(Debug, Clone)]
+pub struct ApiForm {
+    /// Key of the edited check, `None` when creating one.
+    pub key: Option<String>,
+    pub values: [String; 7],
+    pub focus: usize,
+    pub error: Option<String>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;")
+}
+
+/// Split on the `;` that are not escaped, removing the escapes.
+fn split_pairs(text: &str) -> Vec<String> {
+    let mut pairs = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next @ (';' | '\\')) => pairs.last_mut().unwrap().push(next),
+                Some(next) => pairs.last_mut().unwrap().extend(['\\', next]),
+                None => pairs.last_mut().unwrap().push('\\'),
+            },
+            ';' => pairs.push(String::new()),
+            c => pairs.last_mut().unwrap().push(c),
+        }
+    }
+    pairs
+}
+
+fn format_pairs(pairs: &Option<ReqHash>) -> String {
+    let mut pairs: Vec<String> = pairs
+        .iter()
+        .flatten()
+        .map(|(key, value)| format!("{}: {}", escape(key), escape(value)))
+        .collect();
+    pairs.sort();
+    pairs.join("; ")
+}
+
+fn parse_pairs(field: &str, text: &str) -> Result<Option<ReqHash>, String> {
+    let mut pairs = ReqHash::new();
+    for pair in split_pairs(text)
+        .iter()
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+    {
+        let (key, value) = pair
+            .split_once(':')
+            .ok_or_else(|| format!("{field}: expected `key: value`, got `{pair}`"))?;
+        pairs.insert(key.trim().to_string(), value.trim().to_string());
+    }
+
+    Ok((!pairs.is_empty()).then_some(pairs))
+}
+
+impl ApiForm {
+    pub fn new() -> ApiForm {
+        ApiForm {
+            key: None,
+            values: [
+                String::new(),
+                String::from("https://"),
+                String::from("GET"),
+                String::new(),
+                String::new(),
+                String::from("200"),
+                String::from("10"),
+            ],
+            focus: 0,
+            error: None,
+        }
+    }
+
+    pub fn edit(key: &str, api: &Api) -> ApiForm {
+        ApiForm {
+            key: Some(key.to_string()),
+            values: [
+                api.name.to_string(),
+                api.request.url.to_string(),
+                api.request.method.to_string(),
+                format_pairs(&api.request.headers),
+                format_pairs(&api.request.body),
+                api.expected_status.to_string(),
+                api.interval.to_string(),
+            ],
+            focus: 0,
+            error: None,
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> FormAction {
+        match key.code {
+            KeyCode::Esc => return FormAction::Cancel,
+            KeyCode::Enter => return FormAction::Submit,
+            KeyCode::Tab | KeyCode::Down => self.focus = (self.focus + 1) % FIELDS.len(),
+            KeyCode::BackTab | KeyCode::Up => {
+                self.focus = (self.focus + FIELDS.len() - 1) % FIELDS.len()
+            }
+            KeyCode::Backspace => {
+                self.values[self.focus].pop();
+            }
+            KeyCode::Char(c) => self.values[self.focus].push(c),
+            _ => {}
+        }
+        FormAction::None
+    }
+
+    /// Build the check from the form. Settings the form does not show are
+    /// taken from `base`, the check being edited. `steps` are the shared
+    /// `depends_on` steps it may use and `requests` the other checks.
+    pub fn to_api(
+        &self,
+        base: Option<&Api>,
+        steps: &HashMap<String, Depends>,
+        requests: &HashMap<String, Api>,
+    ) -> Result<Api, String> {
+        let name = self.values[NAME].trim();
+        if name.is_empty() {
+            return Err(String::from("Name is required"));
+        }
+
+        let url = self.values[URL].trim();
+
+        let method = self.values[METHOD].trim();
+        if method.is_empty() {
+            return Err(String::from("Method is required"));
+        }
+
+        let headers = parse_pairs("Headers", &self.values[HEADERS])?;
+        let body = parse_pairs("Body", &self.values[BODY])?;
+
+        let expected_status = self.values[EXPECTED_STATUS]
+            .trim()
+            .parse::<u16>()
+            .map_err(|_| String::from("Expected status must be a number"))?;
+        let interval = match self.values[INTERVAL].trim().parse::<u64>() {
+            Ok(interval) if interval > 0 => interval,
+            _ => return Err(String::from("Interval must be a number above 0")),
+        };
+
+        let request = Request {
+            url: url.to_string(),
+            // Query and auth are not editable in the form, they are kept from
+            // the check being edited.
+            query: base.and_then(|base| base.request.query.clone()),
+            headers,
+            method: ConfigMethod::from(method.to_string()),
+            body,
+            auth: base.and_then(|base| base.request.auth.clone()),
+        };
+
+        let api = match base {
+            Some(base) => Api {
+                name: name.to_string(),
+                request,
+                expected_status,
+                interval,
+                ..base.clone()
+            },
+            None => Api {
+                name: name.to_string(),
+                tags: Vec::new(),
+                group: None,
+                depends_on: None,
+                request,
+                expected_status,
+                assertions: Vec::new(),
+                warn_latency_ms: None,
+                max_latency_ms: None,
+                timeout: default_timeout(),
+                retries: 0,
+                retry_backoff: RetryBackoff::default(),
+                interval,
+                system_notify: false,
+                notify_type: String::new(),
+            },
+        };
+
+        let key = self.key.as_deref().unwrap_or("new");
+        match validate::check_api(key, &api, steps).into_iter().next() {
+            Some((path, message)) => {
+                let field = path.trim_start_matches(&format!("requests.{key}."));
+                Err(format!("{field}: {message}"))
+            }
+            None => match validate::duplicate_name(self.key.as_deref(), &api, requests) {
+                Some(message) => Err(format!("name: {message}")),
+                None => Ok(api),
+            },
+        }
+    }
+}
+
+/// Config key for a new check named `name`, unique among `existing`.
+pub fn new_key<'a>(name: &str, existing: impl Iterator<Item = &'a String> + Clone) -> String {
+    let base: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let base = if base.is_empty() {
+        String::from("check")
+    } else {
+        base
+    };
+
+    let mut key = base.clone();
+    let mut suffix = 2;
+    while existing.clone().any(|k| *k == key) {
+        key = format!("{base}_{suffix}");
+        suffix += 1;
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_with_semicolons_round_trip() {
+        let headers = ReqHash::from([
+            (
+                String::from("content-type"),
+                String::from("text/html; charset=utf-8"),
+            ),
+            (String::from("x-path"), String::from(r"C:\tmp;\x")),
+        ]);
+
+        let text = format_pairs(&Some(headers.clone()));
+        assert_eq!(
+            text,
+            r"content-type: text/html\; charset=utf-8; x-path: C:\\tmp\;\\x"
+        );
+        assert_eq!(parse_pairs("Headers", &text), Ok(Some(headers)));
+    }
+}
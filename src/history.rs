@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::check::{CheckResult, CheckState};
+
+/// Bounded list of the last results of one check, oldest first.
+#[derive(Debug, Clone)]
+pub struct History {
+    results: VecDeque<CheckResult>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            results: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Store a finished result, dropping the oldest one when full.
+    /// Pending results and results already stored are ignored; returns
+    /// whether the result was added.
+    pub fn push(&mut self, result: &CheckResult) -> bool {
+        let Some(checked_at) = result.checked_at else {
+            return false;
+        };
+        if self.last().and_then(|r| r.checked_at) >= Some(checked_at) {
+            return false;
+        }
+
+        if self.results.len() == self.capacity {
+            self.results.pop_front();
+        }
+        // The response is only needed for the latest result.
+        self.results.push_back(CheckResult {
+            response: None,
+            ..result.clone()
+        });
+        true
+    }
+
+    pub fn last(&self) -> Option<&CheckResult> {
+        self.results.back()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &CheckResult> {
+        self.results.iter()
+    }
+
+    fn since(&self, window: Option<Duration>) -> impl Iterator<Item = &CheckResult> {
+        let start = window
+            .and_then(|w| chrono::Duration::from_std(w).ok())
+            .map(|w| Utc::now() - w);
+
+        self.results
+            .iter()
+            .filter(move |r| start.is_none() || r.checked_at >= start)
+    }
+
+    /// Percentage of checks that reached the endpoint and were up (or
+    /// degraded) in the last `window`, or over the whole buffer when `None`.
+    pub fn uptime(&self, window: Option<Duration>) -> Option<f64> {
+        let (up, total) = self
+            .since(window)
+            .filter(|r| r.state != CheckState::Unknown)
+            .fold((0, 0), |(up, total), r| {
+                let is_up = matches!(r.state, CheckState::Up | CheckState::Degraded);
+                (up + usize::from(is_up), total + 1)
+            });
+
+        (total > 0).then(|| up as f64 * 100.0 / total as f64)
+    }
+
+    fn latencies(&self) -> Vec<Duration> {
+        self.results.iter().filter_map(|r| r.latency()).collect()
+    }
+
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let latencies = self.latencies();
+        let count = u32::try_from(latencies.len()).ok().filter(|c| *c > 0)?;
+
+        Some(latencies.iter().sum::<Duration>() / count)
+    }
+
+    /// 95th percentile latency, nearest-rank method.
+    pub fn p95_latency(&self) -> Option<Duration> {
+        let mut latencies = self.latencies();
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort();
+
+        let rank = (latencies.len() * 95).div_ceil(100);
+        latencies.get(rank.saturating_sub(1)).copied()
+    }
+
+    /// Number of times the state changed inside the buffer.
+    pub fn state_changes(&self) -> usize {
+        self.results
+            .iter()
+            .zip(self.results.iter().skip(1))
+            .filter(|(a, b)| a.state != b.state)
+            .count()
+    }
+
+    /// When the check entered its current state. If it never changed inside
+    /// the buffer, this is the time of the oldest result.
+    pub fn last_state_change(&self) -> Option<DateTime<Utc>> {
+        let current = self.last()?.state;
+
+        self.results
+            .iter()
+            .rev()
+            .take_while(|r| r.state == current)
+            .last()
+            .and_then(|r| r.checked_at)
+    }
+
+    pub fn time_since_state_change(&self) -> Option<Duration> {
+        self.last_state_change()
+            .and_then(|changed| (Utc::now() - changed).to_std().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Timings;
+
+    fn result(seconds_ago: i64, latency_ms: u64) -> CheckResult {
+        CheckResult {
+            state: CheckState::Up,
+            timings: Some(Timings {
+                total: Duration::from_millis(latency_ms),
+                ..Timings::default()
+            }),
+            checked_at: Some(Utc::now() - chrono::Duration::seconds(seconds_ago)),
+            ..CheckResult::pending()
+        }
+    }
+
+    #[test]
+    fn p95_latency_uses_nearest_rank() {
+        let mut history = History::new(100);
+        assert_eq!(history.p95_latency(), None);
+
+        for ms in (1..=20).rev() {
+            history.push(&result(ms as i64, ms * 10));
+        }
+        assert_eq!(history.p95_latency(), Some(Duration::from_millis(190)));
+
+        history.push(&result(0, 1000));
+        assert_eq!(history.p95_latency(), Some(Duration::from_millis(200)));
+    }
+}
@@ -0,0 +1,201 @@
+use regex::{Captures, Regex};
+use std::env;
+use std::fs;
+
+use crate::utils::yarn::{Api, Depends, DependsOn, ReqHash, Request, Step};
+
+/// Shown instead of a resolved value.
+pub const MASK: &str = "********";
+
+/// Values shorter than this are not masked, they would hide too much text.
+const MIN_SECRET_LEN: usize = 3;
+
+/// Values resolved from the environment or from files while expanding a
+/// check, and the credentials it sent, to be masked wherever the check
+/// output is shown.
+#[derive(Debug, Clone, Default)]
+pub struct Secrets(Vec<String>);
+
+impl Secrets {
+    pub fn add(&mut self, value: &str) {
+        if value.len() >= MIN_SECRET_LEN && !self.0.iter().any(|s| s == value) {
+            self.0.push(value.to_string());
+        }
+    }
+
+    /// Replace every resolved value in `text`.
+    pub fn mask(&self, text: &str) -> String {
+        let mut secrets: Vec<&String> = self.0.iter().collect();
+        // Longest first, in case one value contains another.
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
+        let mut text = text.to_string();
+        for secret in secrets {
+            text = text.replace(secret.as_str(), MASK);
+        }
+        text
+    }
+}
+
+/// Expand `${NAME}`, `${NAME:-default}` and `${file:/path}` in `text`.
+/// `$${` is kept as a literal `${`.
+pub fn expand(text: &str, secrets: &mut Secrets) -> Result<String, String> {
+    let variable = Regex::new(r"\$?\$\{([^}]*)\}").unwrap();
+    let mut error = None;
+
+    let expanded = variable.replace_all(text, |caps: &Captures| {
+        if caps[0].starts_with("$$") {
+            return caps[0][1..].to_string();
+        }
+
+        let expression = &caps[1];
+        let value = if let Some(path) = expression.strip_prefix("file:") {
+            fs::read_to_string(path)
+                .map(|content| content.trim_end_matches(['\n', '\r']).to_string())
+                .map_err(|e| format!("cannot read secret file {path}: {e}"))
+        } else {
+            let (name, default) = match expression.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (expression, None),
+            };
+            match (env::var(name), default) {
+                (Ok(value), _) => Ok(value),
+                (Err(_), Some(default)) => return default.to_string(),
+                (Err(_), None) => Err(format!("environment variable {name} is not set")),
+            }
+        };
+
+        match value {
+            Ok(value) => {
+                secrets.add(&value);
+                value
+            }
+            Err(e) => {
+                error.get_or_insert(e);
+                String::new()
+            }
+        }
+    });
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+fn expand_map(pairs: &ReqHash, secrets: &mut Secrets) -> Result<ReqHash, String> {
+    pairs
+        .iter()
+        .map(|(key, value)| Ok((expand(key, secrets)?, expand(value, secrets)?)))
+        .collect()
+}
+
+fn expand_pairs(pairs: &Option<ReqHash>, secrets: &mut Secrets) -> Result<Option<ReqHash>, String> {
+    pairs
+        .as_ref()
+        .map(|pairs| expand_map(pairs, secrets))
+        .transpose()
+}
+
+fn expand_request(request: &Request, secrets: &mut Secrets) -> Result<Request, String> {
+    Ok(Request {
+        url: expand(&request.url, secrets)?,
+        query: expand_pairs(&request.query, secrets)?,
+        headers: expand_pairs(&request.headers, secrets)?,
+        method: request.method.clone(),
+        body: expand_pairs(&request.body, secrets)?,
+        auth: request
+            .auth
+            .as_ref()
+            .map(|auth| auth.map_text(|_, text| expand(text, secrets)))
+            .transpose()?,
+    })
+}
+
+pub fn expand_depends(depends: &Depends, secrets: &mut Secrets) -> Result<Depends, String> {
+    Ok(Depends {
+        name: expand(&depends.name, secrets)?,
+        header_fields: depends
+            .header_fields
+            .iter()
+            .map(|field| expand(field, secrets))
+            .collect::<Result<_, _>>()?,
+        body_fields: depends
+            .body_fields
+            .iter()
+            .map(|field| expand(field, secrets))
+            .collect::<Result<_, _>>()?,
+        request: expand_request(&depends.request, secrets)?,
+        extract: expand_map(&depends.extract, secrets)?,
+        cache_ttl: depends.cache_ttl,
+    })
+}
+
+fn expand_step(step: &Step, secrets: &mut Secrets) -> Result<Step, String> {
+    Ok(match step {
+        Step::Request(depends) => Step::Request(Box::new(expand_depends(depends, secrets)?)),
+        Step::Shared(name) => Step::Shared(name.to_string()),
+    })
+}
+
+fn expand_depends_on(depends_on: &DependsOn, secrets: &mut Secrets) -> Result<DependsOn, String> {
+    Ok(match depends_on {
+        DependsOn::One(step) => DependsOn::One(expand_step(step, secrets)?),
+        DependsOn::Chain(steps) => DependsOn::Chain(
+            steps
+                .iter()
+                .map(|step| expand_step(step, secrets))
+                .collect::<Result<_, _>>()?,
+        ),
+    })
+}
+
+/// Copy of `api` with its request and `depends_on` expanded, as sent over
+/// the wire. The config itself keeps the `${...}` references so resolved
+/// values are never displayed or saved.
+pub fn expand_api(api: &Api, secrets: &mut Secrets) -> Result<Api, String> {
+    Ok(Api {
+        request: expand_request(&api.request, secrets)?,
+        depends_on: api
+            .depends_on
+            .as_ref()
+            .map(|depends_on| expand_depends_on(depends_on, secrets))
+            .transpose()?,
+        ..api.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_variables_and_defaults() {
+        env::set_var("HEALTH_CRAB_TEST_HOST", "example.com");
+        env::remove_var("HEALTH_CRAB_TEST_UNSET");
+        let mut secrets = Secrets::default();
+
+        let expanded = expand(
+            "https://${HEALTH_CRAB_TEST_HOST}/${HEALTH_CRAB_TEST_UNSET:-health}",
+            &mut secrets,
+        );
+        assert_eq!(expanded.unwrap(), "https://example.com/health");
+        assert_eq!(secrets.mask("at example.com"), format!("at {MASK}"));
+
+        assert_eq!(
+            expand("${HEALTH_CRAB_TEST_UNSET}", &mut secrets),
+            Err(String::from(
+                "environment variable HEALTH_CRAB_TEST_UNSET is not set"
+            ))
+        );
+    }
+
+    #[test]
+    fn double_dollar_is_literal() {
+        let mut secrets = Secrets::default();
+        assert_eq!(
+            expand("$${HEALTH_CRAB_TEST_UNSET} costs $5", &mut secrets).unwrap(),
+            "${HEALTH_CRAB_TEST_UNSET} costs $5"
+        );
+    }
+}
@@ -1,28 +1,50 @@
+mod assertion;
+mod auth;
+mod check;
+mod connection;
+mod control;
+mod depends_cache;
+mod form;
+mod history;
+mod interpolate;
+mod metrics;
+mod oneshot;
+mod output;
+mod reload;
 mod request;
+mod scheduler;
+mod server;
+mod status_page;
+mod storage;
+mod template;
 mod utils;
-
-use app::{ApiInformation, ListRequests};
-use request::{get_depends_result, request_api};
-
-use utils::notify::send_notify;
-use utils::yarn::{Api, ApisConfig, ReqHash};
-
+mod validate;
+mod view;
+
+use app::{Mode, PAGE};
+use control::{ControlCommand, ControlReceiver};
+use form::{ApiForm, FormAction};
+use oneshot::{run_once, CheckFilter};
+use output::{format_results, CheckReport, OutputFormat};
+use reload::ConfigWatcher;
+use scheduler::Scheduler;
+use storage::Storage;
+use view::View;
+
+use utils::yarn::ApisConfig;
+use validate::Problem;
+
+use axum::Router;
 use clap::Arg;
+use clap::ArgAction;
+use clap::ArgMatches;
 use clap::Command;
-use futures::executor::block_on;
-use reqwest::header::HeaderMap;
-use reqwest::header::HeaderName;
-use serde_json::Value;
 use std::collections::HashMap;
-use std::convert::TryInto;
 use std::fs;
-use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{error::Error, io};
-use tokio::task;
+use tokio::sync::mpsc;
+use tracing_subscriber::EnvFilter;
 
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, KeyCode},
@@ -38,206 +60,481 @@ mod app;
 mod ui;
 use crate::{app::App, ui::ui};
 
-async fn verify_api(api: &Api) -> ApiInformation {
-    let mut headers_map = HeaderMap::new();
+fn load_config(config_path: &str) -> Result<ApisConfig, Vec<Problem>> {
+    eprintln!("Searching for {}", config_path);
 
-    let fields_required = match &api.depends_on {
-        Some(depends) => block_on(get_depends_result(&depends, api.system_notify)),
-        None => HashMap::new(),
-    };
+    validate::load(config_path)
+}
 
-    for header in &api.request.headers {
-        for (key, value) in header {
-            if fields_required.len() > 0 {
-                for results in &fields_required["depends_headers"] {
-                    for (field_name, field_value) in results {
-                        let field = &format!("{{{}}}", field_name);
-
-                        if let Some(_) = value.find(field) {
-                            let replace_value = value.replace(field, &field_value);
-                            headers_map.insert(
-                                HeaderName::from_str(&key).unwrap(),
-                                replace_value.parse().unwrap(),
-                            );
-                        } else if headers_map.get(key) == None {
-                            headers_map.insert(
-                                HeaderName::from_str(&key).unwrap(),
-                                value.parse().unwrap(),
-                            );
-                        }
-                    }
+fn print_problems(config_path: &str, problems: &[Problem]) {
+    for problem in problems {
+        eprintln!("{config_path}: {problem}");
+    }
+}
+
+async fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    mut control: Option<ControlReceiver>,
+    mut watcher: ConfigWatcher,
+    save_path: Option<&str>,
+) -> io::Result<bool> {
+    let mut scheduler = Scheduler::new(app.apis_infos.clone());
+
+    loop {
+        terminal.draw(|f| ui(f, app))?;
+
+        if let Some(receiver) = &mut control {
+            control::drain(app, &mut scheduler, receiver);
+        }
+        match watcher.reload(app, &mut scheduler) {
+            Some(Ok(_)) => app.notice = None,
+            Some(Err(e)) => app.notice = Some(e),
+            None => {}
+        }
+        scheduler.tick(&app.configs);
+        app.append_status(scheduler.results());
+
+        if event::poll(std::time::Duration::from_millis(16))? {
+            if let event::Event::Key(key) = event::read()? {
+                if key.kind == event::KeyEventKind::Release {
+                    // Skip events that are not KeyEventKind::Press
+                    continue;
+                }
+                if handle_key(app, &mut scheduler, key, save_path) {
+                    scheduler.stop();
+                    return Ok(true);
                 }
-            } else {
-                headers_map.insert(HeaderName::from_str(&key).unwrap(), value.parse().unwrap());
             }
         }
     }
+}
 
-    let body = if fields_required.len() > 0 {
-        let mut custom_body = ReqHash::new();
+/// Write the checks back to the config file. Comments and formatting of
+/// the original file are not kept.
+fn save_config(path: &str, configs: &ApisConfig) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_yml::to_string(configs)?)?;
+    Ok(())
+}
 
-        for body in &api.request.body {
-            for (key, value) in body {
-                for results in &fields_required["depends_body"] {
-                    for (field_name, field_value) in results {
-                        let field = &format!("{{{}}}", field_name);
+/// How long `m` mutes the notifications of a check.
+const MUTE_FOR: Duration = Duration::from_secs(60 * 60);
 
-                        let value_converted: String = serde_json::from_str(&field_value).unwrap();
+/// Run a control command on behalf of the TUI, showing its error if any.
+fn run_command(app: &mut App, scheduler: &mut Scheduler, command: ControlCommand) {
+    app.notice = match control::apply(app, scheduler, command) {
+        Ok(_) => None,
+        Err((_, message)) => Some(message),
+    };
+}
 
-                        if let Some(_) = value.find(field) {
-                            let replace_value = value.replace(field, &value_converted);
+/// Apply a change made in the TUI the same way the control API does, then
+/// save the config when `--save` was given.
+fn apply_change(
+    app: &mut App,
+    scheduler: &mut Scheduler,
+    command: ControlCommand,
+    save_path: Option<&str>,
+) {
+    run_command(app, scheduler, command);
+
+    if let Some(path) = save_path {
+        if let Err(e) = save_config(path, &app.configs) {
+            app.notice = Some(format!("failed to save {path}: {e}"));
+        }
+    }
+}
 
-                            custom_body.insert(key.to_owned(), replace_value);
-                        } else if body.get(key) == None {
-                            custom_body.insert(key.to_string(), value.to_string());
-                        }
+/// Handle a key press. Returns true when the app should quit.
+fn handle_key(
+    app: &mut App,
+    scheduler: &mut Scheduler,
+    key: event::KeyEvent,
+    save_path: Option<&str>,
+) -> bool {
+    match &mut app.mode {
+        Mode::Normal => match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Down | KeyCode::Char('j') => app.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => app.move_selection(-1),
+            KeyCode::PageDown => app.move_selection(PAGE),
+            KeyCode::PageUp => app.move_selection(-PAGE),
+            KeyCode::Home | KeyCode::Char('g') => app.select_first(),
+            KeyCode::End | KeyCode::Char('G') => app.select_last(),
+            KeyCode::Char('/') => app.mode = Mode::Search,
+            KeyCode::Char('f') => app.view.next_filter(&app.configs.requests),
+            KeyCode::Char('s') => app.view.next_sort(),
+            KeyCode::Esc => app.view = View::default(),
+            KeyCode::Enter => {
+                if let Some(row) = app.selected_row() {
+                    app.mode = Mode::Detail {
+                        key: row.key.to_string(),
+                        scroll: 0,
+                    };
+                }
+            }
+            KeyCode::Char('a') => app.mode = Mode::Form(ApiForm::new()),
+            KeyCode::Char('e') => {
+                if let Some(row) = app.selected_row() {
+                    if let Some(api) = app.configs.requests.get(&row.key) {
+                        app.mode = Mode::Form(ApiForm::edit(&row.key, api));
                     }
                 }
             }
-        }
-        Some(custom_body)
-    } else {
-        api.request.body.to_owned()
-    };
+            KeyCode::Char('d') => {
+                if let Some(row) = app.selected_row() {
+                    app.mode = Mode::ConfirmDelete(row.key.to_string());
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(row) = app.selected_row() {
+                    let command = ControlCommand::Run(row.key.to_string());
+                    run_command(app, scheduler, command);
+                }
+            }
+            KeyCode::Char('p') => {
+                if let Some(row) = app.selected_row() {
+                    let command = if row.paused {
+                        ControlCommand::Resume(row.key.to_string())
+                    } else {
+                        ControlCommand::Pause(row.key.to_string())
+                    };
+                    run_command(app, scheduler, command);
+                }
+            }
+            KeyCode::Char('m') => {
+                if let Some(row) = app.selected_row() {
+                    let until = (!row.is_muted()).then(|| Instant::now() + MUTE_FOR);
+                    scheduler.set_muted(row.id, until);
+                }
+            }
+            KeyCode::Char('?') => app.mode = Mode::Help,
+            _ => {}
+        },
+        Mode::Help => app.mode = Mode::Normal,
+        Mode::Form(form) => match form.handle_key(key) {
+            FormAction::None => {}
+            FormAction::Cancel => app.mode = Mode::Normal,
+            FormAction::Submit => {
+                let base = form.key.as_ref().and_then(|k| app.configs.requests.get(k));
+                match form.to_api(base, &app.configs.steps, &app.configs.requests) {
+                    Ok(api) => {
+                        let key = form.key.clone().unwrap_or_else(|| {
+                            form::new_key(&api.name, app.configs.requests.keys())
+                        });
+                        app.mode = Mode::Normal;
+                        apply_change(
+                            app,
+                            scheduler,
+                            ControlCommand::Put(key, Box::new(api)),
+                            save_path,
+                        );
+                    }
+                    Err(message) => form.error = Some(message),
+                }
+            }
+        },
+        Mode::ConfirmDelete(check_key) => match key.code {
+            KeyCode::Char('y') => {
+                let command = ControlCommand::Delete(check_key.to_string());
+                app.mode = Mode::Normal;
+                apply_change(app, scheduler, command, save_path);
+                app.move_selection(0);
+            }
+            KeyCode::Char('n') | KeyCode::Esc => app.mode = Mode::Normal,
+            _ => {}
+        },
+        Mode::Search => match key.code {
+            KeyCode::Enter => app.mode = Mode::Normal,
+            KeyCode::Esc => {
+                app.view.query.clear();
+                app.mode = Mode::Normal;
+            }
+            KeyCode::Backspace => {
+                app.view.query.pop();
+            }
+            KeyCode::Char(c) => app.view.query.push(c),
+            _ => {}
+        },
+        Mode::Detail { scroll, .. } => match key.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => app.mode = Mode::Normal,
+            KeyCode::Down | KeyCode::Char('j') => *scroll = scroll.saturating_add(1),
+            KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+            KeyCode::PageDown => *scroll = scroll.saturating_add(PAGE as u16),
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(PAGE as u16),
+            KeyCode::Home | KeyCode::Char('g') => *scroll = 0,
+            _ => {}
+        },
+    }
+    false
+}
 
-    let response = request_api(&api.request, headers_map, &body).await;
+/// Run the checks without a terminal, logging every state change. With
+/// `json_lines`, every new result is also printed to stdout as one JSON line.
+async fn run_headless(
+    app: &mut App,
+    mut control: Option<ControlReceiver>,
+    mut watcher: ConfigWatcher,
+    json_lines: bool,
+) {
+    let mut scheduler = Scheduler::new(app.apis_infos.clone());
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
 
-    let status = match response {
-        Ok(resp) => resp.status().as_u16(),
-        Err(_) => 500,
-    };
+    tracing::info!("monitoring {} checks", app.configs.requests.len());
 
-    let mut request_data = ApiInformation {
-        name: api.name.to_string(),
-        url: api.request.url.to_string(),
-        method: api.request.method.to_string(),
-        status: "WAINTING".to_string(),
-    };
+    loop {
+        if let Some(receiver) = &mut control {
+            control::drain(app, &mut scheduler, receiver);
+        }
+        match watcher.reload(app, &mut scheduler) {
+            Some(Ok(changes)) => tracing::info!("config reloaded, {changes} checks changed"),
+            Some(Err(e)) => tracing::error!("{e}"),
+            None => {}
+        }
+        scheduler.tick(&app.configs);
+        let new_results = app.append_status(scheduler.results());
 
-    if status == api.expected_status {
-        if api.notify_type != "ERROR" {
-            request_data.status = "OK".to_string();
+        if json_lines {
+            for request in &new_results {
+                let report = CheckReport::new(&request.key, &request.data);
+                println!("{}", serde_json::to_string(&report).unwrap());
+            }
         }
-    } else {
-        request_data.status = "ERROR".to_string();
-        if api.system_notify == true {
-            let notify_message = format!("Request failed with status {status}");
-            send_notify(api.name.as_str(), "dialog-error", notify_message.as_str()).unwrap();
+
+        tokio::select! {
+            _ = &mut shutdown => {
+                tracing::info!("shutting down");
+                scheduler.stop();
+                return;
+            }
+            _ = tokio::time::sleep(Duration::from_millis(200)) => {}
         }
     }
+}
 
-    request_data
+/// Resolves on Ctrl-C, or on SIGTERM when running under a service manager.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
 }
 
-fn load_config(config_path: &str) -> Result<ApisConfig, serde_yml::Error> {
-    println!("Searching for {}", config_path);
+/// Start the configured HTTP endpoints. Endpoints sharing a listen address
+/// are served by the same listener. Returns the receiving end of the control
+/// API when it is enabled.
+async fn start_servers(configs: &ApisConfig, app: &App) -> io::Result<Option<ControlReceiver>> {
+    let mut routers: HashMap<String, Router> = HashMap::new();
+
+    if let Some(metrics_config) = &configs.metrics {
+        let router = metrics::router(&metrics_config.path, app.metrics.clone());
+        let merged = routers.remove(&metrics_config.listen).unwrap_or_default();
+        routers.insert(metrics_config.listen.to_string(), merged.merge(router));
+    }
+
+    if let Some(page_config) = &configs.status_page {
+        let router = status_page::router(page_config.clone(), app.status_page.clone());
+        let merged = routers.remove(&page_config.listen).unwrap_or_default();
+        routers.insert(page_config.listen.to_string(), merged.merge(router));
+    }
 
-    let config_str_content =
-        fs::read_to_string(config_path).expect("Something went wrong reading the file");
+    let mut control = None;
+    if let Some(control_config) = &configs.control {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let router = control::router(control_config, sender);
+        let merged = routers.remove(&control_config.listen).unwrap_or_default();
+        routers.insert(control_config.listen.to_string(), merged.merge(router));
+        control = Some(receiver);
+    }
 
-    let deserialized = serde_yml::from_str::<ApisConfig>(&config_str_content);
+    for (listen, router) in routers {
+        server::spawn(&listen, router).await?;
+    }
 
-    deserialized
+    Ok(control)
 }
 
-async fn run_app<B: Backend>(
-    terminal: &mut Terminal<B>,
-    app: &mut App,
-    configs: &HashMap<String, Api>,
-) -> io::Result<bool> {
-    let mut handles: HashMap<usize, task::JoinHandle<()>> = HashMap::new();
+fn file_arg() -> Arg {
+    Arg::new("file")
+        .short('f')
+        .long("file")
+        .help("config file with APIs")
+}
 
-    let results = Arc::new(Mutex::new(app.apis_infos.clone()));
-    let running = Arc::new(AtomicBool::new(true));
+fn output_arg() -> Arg {
+    Arg::new("output")
+        .short('o')
+        .long("output")
+        .value_parser(OutputFormat::NAMES)
+        .default_value("table")
+        .help("format of the results printed to stdout")
+}
 
-    loop {
-        terminal.draw(|f| ui(f, app))?;
+fn output_format(matches: &ArgMatches) -> OutputFormat {
+    matches
+        .get_one::<String>("output")
+        .and_then(|format| format.parse().ok())
+        .unwrap_or(OutputFormat::Table)
+}
 
-        for (id, config) in configs.iter().enumerate() {
-            if handles.contains_key(&id) {
-                if handles[&id].is_finished() {
-                    handles.remove(&id);
-                }
-            }
+/// `check` subcommand. Returns the process exit code: 0 when every check
+/// passed, 1 when one failed and 2 when nothing could be run.
+async fn run_check_command(matches: &ArgMatches) -> i32 {
+    let config_path = matches.get_one::<String>("file").unwrap();
 
-            if !handles.contains_key(&id) {
-                let results = Arc::clone(&results);
-                let api_config = config.1.clone();
-                let running = running.clone();
-
-                let handle = task::spawn(async move {
-                    let mut counter = api_config.interval;
-                    while running.load(Ordering::SeqCst) {
-                        thread::sleep(Duration::from_secs(1));
-                        counter -= 1;
-
-                        if counter < 1 {
-                            let status_api: app::ApiInformation = verify_api(&api_config).await;
-
-                            let mut results = results.lock().unwrap();
-                            let new_request = ListRequests {
-                                id: id.try_into().unwrap(),
-                                data: status_api,
-                                interval: api_config.interval,
-                            };
-                            if let Some(status) = results.iter_mut().find(|r| r.id == id) {
-                                *status = new_request;
-                            } else {
-                                results.push(new_request);
-                            }
-                            break;
-                        }
-
-                        let mut results = results.lock().unwrap();
-                        if let Some(status) = results.iter_mut().find(|r| r.id == id) {
-                            status.interval = counter;
-                        }
-                    }
-                });
-                handles.insert(id, handle);
-            }
+    let configs = match load_config(config_path) {
+        Ok(configs) => configs,
+        Err(problems) => {
+            print_problems(config_path, &problems);
+            return 2;
         }
+    };
 
-        let results = results.lock().unwrap();
-        app.append_status(results.clone());
+    let values = |id: &str| -> Vec<String> {
+        matches
+            .get_many::<String>(id)
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default()
+    };
+    let filter = CheckFilter {
+        names: values("only"),
+        tags: values("tag"),
+    };
 
-        if event::poll(std::time::Duration::from_millis(16))? {
-            if let event::Event::Key(key) = event::read()? {
-                if key.kind == event::KeyEventKind::Release {
-                    // Skip events that are not KeyEventKind::Press
-                    continue;
-                }
-                match key.code {
-                    KeyCode::Char('q') => {
-                        running.store(false, Ordering::SeqCst);
-                        return Ok(true);
-                    }
-                    _ => {}
-                }
-            }
-        }
+    let results = run_once(&configs, &filter).await;
+    if results.is_empty() {
+        eprintln!("No checks matched the filters");
+        return 2;
+    }
+
+    print!("{}", format_results(output_format(matches), &results));
+
+    if results.iter().any(|(_, data)| data.result.is_failure()) {
+        1
+    } else {
+        0
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // Variables referenced as `${NAME}` in the config can be set in `.env`.
+    dotenv::dotenv().ok();
+
     let cmd_matches = Command::new("Health Crab TUI")
         .version("0.1.0")
         .author("Elton de Andrade Rodrigues <xxxxxxxxxxxx@xx>")
         .about("Verify API status")
+        .arg(file_arg())
+        .arg(output_arg().help("with --headless, json prints every result as a JSON line"))
+        .arg(
+            Arg::new("headless")
+                .long("headless")
+                .action(ArgAction::SetTrue)
+                .help("run the checks without the TUI, logging state changes"),
+        )
         .arg(
-            Arg::new("file")
-                .short('f')
-                .long("file")
-                .help("config file with APIs"),
+            Arg::new("save")
+                .long("save")
+                .action(ArgAction::SetTrue)
+                .help("write checks added, edited or deleted in the TUI back to the config file"),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Run every check once and exit non-zero if any fails")
+                .arg(file_arg().required(true))
+                .arg(output_arg())
+                .arg(
+                    Arg::new("only")
+                        .long("only")
+                        .action(ArgAction::Append)
+                        .help("run only the check with this name or key (repeatable)"),
+                )
+                .arg(
+                    Arg::new("tag")
+                        .long("tag")
+                        .action(ArgAction::Append)
+                        .help("run only checks with this tag (repeatable)"),
+                ),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check the config file and report every problem with its line")
+                .arg(file_arg().required(true)),
         )
         .get_matches();
 
+    if let Some(check_matches) = cmd_matches.subcommand_matches("check") {
+        let code = run_check_command(check_matches).await;
+        std::process::exit(code);
+    }
+
+    if let Some(validate_matches) = cmd_matches.subcommand_matches("validate") {
+        let config_path = validate_matches.get_one::<String>("file").unwrap();
+        match validate::load(config_path) {
+            Ok(configs) => {
+                println!("{config_path}: OK, {} checks", configs.requests.len());
+                return Ok(());
+            }
+            Err(problems) => {
+                print_problems(config_path, &problems);
+                std::process::exit(1);
+            }
+        }
+    }
+
     let config_path = cmd_matches
         .get_one::<String>("file")
         .unwrap_or_else(|| panic!("File not set"));
 
-    let config_object = load_config(config_path);
+    let configs = match load_config(config_path) {
+        Ok(configs) => configs,
+        Err(problems) => {
+            print_problems(config_path, &problems);
+            std::process::exit(1);
+        }
+    };
+
+    let storage = match &configs.storage {
+        Some(storage_config) => Some(Storage::open(storage_config)?),
+        None => None,
+    };
+
+    let mut app = App::new(configs.clone(), storage);
+    app.format_api_infos();
+
+    let control = start_servers(&configs, &app).await?;
+    let watcher = ConfigWatcher::new(config_path, &configs);
+
+    if cmd_matches.get_flag("headless") {
+        tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+            )
+            .with_writer(io::stderr)
+            .init();
+
+        let json_lines = match output_format(&cmd_matches) {
+            OutputFormat::Json => true,
+            OutputFormat::Table => false,
+            format => {
+                eprintln!("--output {format:?} is only supported by the check subcommand");
+                std::process::exit(2);
+            }
+        };
+
+        run_headless(&mut app, control, watcher, json_lines).await;
+        return Ok(());
+    }
 
     enable_raw_mode()?;
     let mut stdout: io::Stdout = io::stdout();
@@ -245,23 +542,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    match config_object {
-        Ok(configs) => {
-            let mut app = App::new(configs.clone());
-            app.format_api_infos();
-
-            let _ = run_app(&mut terminal, &mut app, &configs.requests).await;
+    let save_path = cmd_matches.get_flag("save").then_some(config_path.as_str());
+    let _ = run_app(&mut terminal, &mut app, control, watcher, save_path).await;
 
-            disable_raw_mode()?;
-            execute!(
-                terminal.backend_mut(),
-                LeaveAlternateScreen,
-                DisableMouseCapture
-            )?;
-            terminal.show_cursor()?;
-        }
-        Err(e) => println!("error parsing: {:?}", e),
-    }
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
 
     Ok(())
 }
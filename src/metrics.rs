@@ -0,0 +1,179 @@
+use axum::http::header::CONTENT_TYPE;
+use axum::routing::get;
+use axum::Router;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::app::ApiInformation;
+use crate::check::CheckState;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Clone)]
+struct CheckSeries {
+    name: String,
+    url: String,
+    state: CheckState,
+    http_status: Option<u16>,
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    latency_count: u64,
+    checks: u64,
+    failures: u64,
+}
+
+impl CheckSeries {
+    fn labels(&self) -> String {
+        format!(
+            "check=\"{}\",url=\"{}\"",
+            escape_label(&self.name),
+            escape_label(&self.url)
+        )
+    }
+}
+
+/// Counters and gauges of every check since startup, rendered in the
+/// Prometheus text exposition format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    series: BTreeMap<String, CheckSeries>,
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    /// Account a new result of the check stored under `key`.
+    pub fn record(&mut self, key: &str, data: &ApiInformation) {
+        let result = &data.result;
+        let series = self
+            .series
+            .entry(key.to_string())
+            .or_insert_with(|| CheckSeries {
+                name: String::new(),
+                url: String::new(),
+                state: CheckState::Pending,
+                http_status: None,
+                buckets: [0; LATENCY_BUCKETS.len()],
+                latency_sum: 0.0,
+                latency_count: 0,
+                checks: 0,
+                failures: 0,
+            });
+
+        series.name = data.name.to_string();
+        series.url = data.url.to_string();
+        series.state = result.state;
+        series.http_status = result.http_status;
+        series.checks += 1;
+        if result.is_failure() {
+            series.failures += 1;
+        }
+
+        if let Some(latency) = result.latency() {
+            let seconds = latency.as_secs_f64();
+            for (bucket, bound) in series.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+                if seconds <= bound {
+                    *bucket += 1;
+                }
+            }
+            series.latency_sum += seconds;
+            series.latency_count += 1;
+        }
+    }
+
+    /// Drop the series of a check that no longer exists.
+    pub fn remove(&mut self, key: &str) {
+        self.series.remove(key);
+    }
+
+    fn write_family(
+        &self,
+        out: &mut String,
+        name: &str,
+        kind: &str,
+        help: &str,
+        value: impl Fn(&CheckSeries) -> String,
+    ) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        for series in self.series.values() {
+            let _ = writeln!(out, "{name}{{{}}} {}", series.labels(), value(series));
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        self.write_family(
+            &mut out,
+            "health_crab_up",
+            "gauge",
+            "Whether the last check passed (1) or failed (0).",
+            |s| u8::from(!matches!(s.state, CheckState::Down | CheckState::Unknown)).to_string(),
+        );
+        self.write_family(
+            &mut out,
+            "health_crab_degraded",
+            "gauge",
+            "Whether the last check was slower than warn_latency_ms.",
+            |s| u8::from(s.state == CheckState::Degraded).to_string(),
+        );
+        self.write_family(
+            &mut out,
+            "health_crab_http_status",
+            "gauge",
+            "HTTP status of the last response, 0 when none was received.",
+            |s| s.http_status.unwrap_or(0).to_string(),
+        );
+        self.write_family(
+            &mut out,
+            "health_crab_checks_total",
+            "counter",
+            "Number of checks run.",
+            |s| s.checks.to_string(),
+        );
+        self.write_family(
+            &mut out,
+            "health_crab_failures_total",
+            "counter",
+            "Number of failed checks.",
+            |s| s.failures.to_string(),
+        );
+
+        let name = "health_crab_latency_seconds";
+        let _ = writeln!(out, "# HELP {name} Total request latency.");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for series in self.series.values() {
+            let labels = series.labels();
+            for (count, bound) in series.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {count}");
+            }
+            let count = series.latency_count;
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {count}");
+            let _ = writeln!(out, "{name}_sum{{{labels}}} {}", series.latency_sum);
+            let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+        }
+
+        out
+    }
+}
+
+/// Router answering scrapes on `path`.
+pub fn router(path: &str, metrics: Arc<Mutex<Metrics>>) -> Router {
+    Router::new().route(
+        path,
+        get(move || async move {
+            let body = metrics.lock().unwrap().render();
+            ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+        }),
+    )
+}
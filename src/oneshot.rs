@@ -0,0 +1,109 @@
+use futures::future::join_all;
+
+use crate::app::ApiInformation;
+use crate::check::verify_api;
+use crate::depends_cache::DependsCache;
+use crate::utils::yarn::{Api, ApisConfig};
+
+/// Which checks a one-shot run should execute. Empty lists match everything.
+#[derive(Debug, Default)]
+pub struct CheckFilter {
+    /// Check names or keys under `requests`.
+    pub names: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl CheckFilter {
+    fn matches(&self, key: &str, api: &Api) -> bool {
+        let name_matches = self.names.is_empty()
+            || self
+                .names
+                .iter()
+                .any(|name| name == key || *name == api.name);
+        let tag_matches =
+            self.tags.is_empty() || self.tags.iter().any(|tag| api.tags.contains(tag));
+
+        name_matches && tag_matches
+    }
+}
+
+/// Run every matching check once, concurrently, and return the results
+/// sorted by name along with the key of each check.
+pub async fn run_once(configs: &ApisConfig, filter: &CheckFilter) -> Vec<(String, ApiInformation)> {
+    let selected: Vec<(&String, &Api)> = configs
+        .requests
+        .iter()
+        .filter(|(key, api)| filter.matches(key, api))
+        .collect();
+
+    // Run concurrently, checks rarely find a step cached yet.
+    let cache = DependsCache::default();
+    let results = join_all(
+        selected
+            .iter()
+            .map(|(_, api)| verify_api(api, &configs.steps, &cache)),
+    )
+    .await;
+
+    let mut results: Vec<(String, ApiInformation)> = selected
+        .into_iter()
+        .map(|(key, _)| key.to_string())
+        .zip(results)
+        .collect();
+    results.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    results
+}
+
+/// Plain text summary of a one-shot run.
+pub fn format_table(results: &[(String, ApiInformation)]) -> String {
+    let rows: Vec<[String; 6]> = results
+        .iter()
+        .map(|(_, data)| {
+            let result = &data.result;
+            [
+                data.name.to_string(),
+                data.method.to_string(),
+                result.state.to_string(),
+                result
+                    .http_status
+                    .map(|status| status.to_string())
+                    .unwrap_or_else(|| String::from("-")),
+                result
+                    .latency()
+                    .map(|latency| format!("{}ms", latency.as_millis()))
+                    .unwrap_or_else(|| String::from("-")),
+                result.error_message.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let header = ["NAME", "METHOD", "STATE", "HTTP", "LATENCY", "ERROR"].map(String::from);
+    let mut widths = header.clone().map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        table.push_str(line.join("  ").trim_end());
+        table.push('\n');
+    }
+
+    let failed = results
+        .iter()
+        .filter(|(_, d)| d.result.is_failure())
+        .count();
+    table.push_str(&format!(
+        "\n{} checks, {} passed, {failed} failed\n",
+        results.len(),
+        results.len() - failed
+    ));
+    table
+}
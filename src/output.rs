@@ -0,0 +1,271 @@
+use serde::Serialize;
+use std::str::FromStr;
+
+use crate::app::ApiInformation;
+use crate::oneshot::format_table;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Junit,
+    Tap,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 4] = ["table", "json", "junit", "tap"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "junit" => Ok(OutputFormat::Junit),
+            "tap" => Ok(OutputFormat::Tap),
+            _ => Err(format!("unknown output format {s}")),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimingsReport {
+    pub dns_ms: Option<f64>,
+    pub connect_ms: Option<f64>,
+    pub tls_ms: Option<f64>,
+    pub ttfb_ms: f64,
+    pub total_ms: f64,
+}
+
+/// JSON representation of one check result.
+#[derive(Serialize, Debug)]
+pub struct CheckReport {
+    pub key: String,
+    pub name: String,
+    pub url: String,
+    pub method: String,
+    pub status: String,
+    pub http_status: Option<u16>,
+    pub latency_ms: Option<f64>,
+    pub timings: Option<TimingsReport>,
+    pub error_kind: Option<String>,
+    pub error_message: Option<String>,
+    pub assertion_failures: Vec<String>,
+    pub attempts: u32,
+    pub checked_at: Option<String>,
+}
+
+fn ms(duration: std::time::Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl CheckReport {
+    pub fn new(key: &str, data: &ApiInformation) -> CheckReport {
+        let result = &data.result;
+
+        CheckReport {
+            key: key.to_string(),
+            name: data.name.to_string(),
+            url: data.url.to_string(),
+            method: data.method.to_string(),
+            status: result.state.to_string(),
+            http_status: result.http_status,
+            latency_ms: result.latency().map(ms),
+            timings: result.timings.as_ref().map(|t| TimingsReport {
+                dns_ms: t.dns.map(ms),
+                connect_ms: t.connect.map(ms),
+                tls_ms: t.tls.map(ms),
+                ttfb_ms: ms(t.ttfb),
+                total_ms: ms(t.total),
+            }),
+            error_kind: result.error_kind.map(|kind| kind.to_string()),
+            error_message: result.error_message.clone(),
+            assertion_failures: result.assertion_failures.clone(),
+            attempts: result.attempts,
+            checked_at: result.checked_at.map(|time| time.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct RunReport {
+    total: usize,
+    passed: usize,
+    failed: usize,
+    checks: Vec<CheckReport>,
+}
+
+fn format_json(results: &[(String, ApiInformation)]) -> String {
+    let failed = results
+        .iter()
+        .filter(|(_, d)| d.result.is_failure())
+        .count();
+    let report = RunReport {
+        total: results.len(),
+        passed: results.len() - failed,
+        failed,
+        checks: results
+            .iter()
+            .map(|(key, data)| CheckReport::new(key, data))
+            .collect(),
+    };
+
+    serde_json::to_string_pretty(&report).unwrap() + "\n"
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn format_junit(results: &[(String, ApiInformation)]) -> String {
+    let failed = results
+        .iter()
+        .filter(|(_, d)| d.result.is_failure())
+        .count();
+    let total_time: f64 = results
+        .iter()
+        .filter_map(|(_, d)| d.result.latency())
+        .map(|latency| latency.as_secs_f64())
+        .sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"health-crab-tui\" tests=\"{}\" failures=\"{failed}\" time=\"{total_time:.3}\">\n",
+        results.len()
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"health-crab-tui\" tests=\"{}\" failures=\"{failed}\" time=\"{total_time:.3}\">\n",
+        results.len()
+    ));
+
+    for (key, data) in results {
+        let result = &data.result;
+        let time = result.latency().map(|l| l.as_secs_f64()).unwrap_or(0.0);
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{time:.3}\"",
+            escape_xml(&data.name),
+            escape_xml(&format!("{key} {} {}", data.method, data.url)),
+        ));
+
+        if result.is_failure() {
+            let kind = result.error_kind.map(|k| k.to_string()).unwrap_or_default();
+            let message = result.error_message.as_deref().unwrap_or("check failed");
+            let mut details = format!("status: {}", result.state);
+            if let Some(code) = result.http_status {
+                details.push_str(&format!("\nhttp status: {code}"));
+            }
+            for failure in &result.assertion_failures {
+                details.push_str(&format!("\nassertion: {failure}"));
+            }
+            xml.push_str(&format!(
+                ">\n      <failure type=\"{}\" message=\"{}\">{}</failure>\n    </testcase>\n",
+                escape_xml(&kind),
+                escape_xml(message),
+                escape_xml(&details)
+            ));
+        } else {
+            xml.push_str(" />\n");
+        }
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Description of a TAP test line: `#` would start a directive and a line
+/// break the next test line.
+fn escape_tap(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('#', "\\#")
+        .replace(['\r', '\n'], " ")
+}
+
+fn format_tap(results: &[(String, ApiInformation)]) -> String {
+    let mut tap = format!("TAP version 13\n1..{}\n", results.len());
+
+    for (number, (_, data)) in results.iter().enumerate() {
+        let result = &data.result;
+        let ok = if result.is_failure() { "not ok" } else { "ok" };
+        tap.push_str(&format!(
+            "{ok} {} - {}\n",
+            number + 1,
+            escape_tap(&data.name)
+        ));
+
+        if result.is_failure() || result.error_message.is_some() {
+            tap.push_str("  ---\n");
+            tap.push_str(&format!("  status: {}\n", result.state));
+            if let Some(code) = result.http_status {
+                tap.push_str(&format!("  http_status: {code}\n"));
+            }
+            if let Some(kind) = result.error_kind {
+                tap.push_str(&format!("  error_kind: {kind}\n"));
+            }
+            if let Some(message) = &result.error_message {
+                tap.push_str(&format!("  message: {}\n", serde_json::json!(message)));
+            }
+            tap.push_str("  ...\n");
+        }
+    }
+
+    tap
+}
+
+pub fn format_results(format: OutputFormat, results: &[(String, ApiInformation)]) -> String {
+    match format {
+        OutputFormat::Table => format_table(results),
+        OutputFormat::Json => format_json(results),
+        OutputFormat::Junit => format_junit(results),
+        OutputFormat::Tap => format_tap(results),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::{CheckResult, CheckState, ErrorKind};
+
+    fn results() -> Vec<(String, ApiInformation)> {
+        vec![(
+            String::from("search"),
+            ApiInformation {
+                name: String::from("Search <\"q\" & 'r'> # 1\nnext"),
+                url: String::from("http://example.com/?a=1&b=2"),
+                method: String::from("GET"),
+                result: CheckResult {
+                    state: CheckState::Down,
+                    error_kind: Some(ErrorKind::Assertion),
+                    error_message: Some(String::from("expected \"<ok>\"\nsecond line")),
+                    ..CheckResult::pending()
+                },
+            },
+        )]
+    }
+
+    #[test]
+    fn junit_escapes_attributes_and_text() {
+        let xml = format_results(OutputFormat::Junit, &results());
+        assert!(xml.contains(
+            "<testcase name=\"Search &lt;&quot;q&quot; &amp; &apos;r&apos;&gt; # 1\nnext\" \
+             classname=\"search GET http://example.com/?a=1&amp;b=2\""
+        ));
+        assert!(xml.contains("message=\"expected &quot;&lt;ok&gt;&quot;\nsecond line\""));
+        assert!(!xml.contains("<ok>"));
+    }
+
+    #[test]
+    fn tap_escapes_descriptions_and_messages() {
+        let tap = format_results(OutputFormat::Tap, &results());
+        assert_eq!(
+            tap.lines().nth(2),
+            Some(r#"not ok 1 - Search <"q" & 'r'> \# 1 next"#)
+        );
+        assert!(tap.contains("  message: \"expected \\\"<ok>\\\"\\nsecond line\"\n"));
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::app::App;
+use crate::control::{self, ControlCommand};
+use crate::scheduler::Scheduler;
+use crate::utils::yarn::{Api, ApisConfig, Depends};
+use crate::validate;
+
+/// How often the modification time of the config file is checked.
+const POLL_EVERY: Duration = Duration::from_secs(1);
+
+/// Watches the config file by polling its modification time.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+    /// Checks and shared steps as last read from the file.
+    loaded: HashMap<String, Api>,
+    loaded_steps: HashMap<String, Depends>,
+}
+
+/// What changed in the file since it was last read.
+struct Changes {
+    commands: Vec<ControlCommand>,
+    /// The new shared steps, when they changed.
+    steps: Option<HashMap<String, Depends>>,
+    /// The checks now in the file.
+    requests: HashMap<String, Api>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &str, loaded: &ApisConfig) -> ConfigWatcher {
+        let path = PathBuf::from(path);
+        ConfigWatcher {
+            modified: modified(&path),
+            path,
+            last_poll: Instant::now(),
+            loaded: loaded.requests.clone(),
+            loaded_steps: loaded.steps.clone(),
+        }
+    }
+
+    /// When the file changed since the last call, read it again and return
+    /// the commands turning the checks it used to define into the new ones.
+    /// Only `requests` and `steps` are reloaded.
+    fn poll(&mut self) -> Option<Result<Changes, String>> {
+        if self.last_poll.elapsed() < POLL_EVERY {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        let configs = validate::load(&self.path.to_string_lossy()).map_err(|problems| {
+            let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+            problems.join("; ")
+        });
+
+        Some(match configs {
+            Ok(configs) => {
+                let mut commands = diff(&self.loaded, &configs.requests);
+                let steps = (configs.steps != self.loaded_steps).then(|| configs.steps.clone());
+
+                // Unchanged checks using a changed step restart with it.
+                for (key, api) in &configs.requests {
+                    let restart = self.loaded.get(key) == Some(api)
+                        && api.depends_on.as_ref().is_some_and(|depends_on| {
+                            changed_steps(&self.loaded_steps, &configs.steps)
+                                .any(|name| depends_on.uses(name))
+                        });
+                    if restart {
+                        commands.push(ControlCommand::Put(key.to_string(), Box::new(api.clone())));
+                    }
+                }
+
+                Ok(Changes {
+                    commands,
+                    steps,
+                    requests: configs.requests,
+                })
+            }
+            Err(e) => Err(format!("failed to reload {}: {e}", self.path.display())),
+        })
+    }
+
+    /// Apply the changes of the config file, if any. Returns the number of
+    /// checks added, changed or removed, or the changes that were refused;
+    /// those are tried again the next time the file changes.
+    pub fn reload(
+        &mut self,
+        app: &mut App,
+        scheduler: &mut Scheduler,
+    ) -> Option<Result<usize, String>> {
+        let Changes {
+            commands,
+            steps,
+            requests,
+        } = match self.poll()? {
+            Ok(changes) => changes,
+            Err(e) => return Some(Err(e)),
+        };
+
+        // Set first, checks are validated against them.
+        if let Some(steps) = steps {
+            app.configs.steps = steps.clone();
+            self.loaded_steps = steps;
+        }
+
+        let mut changes = 0;
+        let mut errors = Vec::new();
+        for command in commands {
+            let (key, applied) = match command {
+                // Names were checked with the whole file, two checks may
+                // swap theirs.
+                ControlCommand::Put(key, api) => {
+                    (key.to_string(), control::put(app, scheduler, key, *api))
+                }
+                ControlCommand::Delete(key) => (
+                    key.to_string(),
+                    control::apply(app, scheduler, ControlCommand::Delete(key)),
+                ),
+                _ => unreachable!("reload only puts and deletes checks"),
+            };
+            match applied {
+                Ok(_) => {
+                    changes += 1;
+                    match requests.get(&key) {
+                        Some(api) => self.loaded.insert(key, api.clone()),
+                        None => self.loaded.remove(&key),
+                    };
+                }
+                Err((_, message)) => errors.push(format!("{key}: {message}")),
+            }
+        }
+
+        if errors.is_empty() {
+            Some(Ok(changes))
+        } else {
+            Some(Err(format!("config reload: {}", errors.join("; "))))
+        }
+    }
+}
+
+/// Names of the shared steps added, changed or removed.
+fn changed_steps<'a>(
+    old: &'a HashMap<String, Depends>,
+    new: &'a HashMap<String, Depends>,
+) -> impl Iterator<Item = &'a String> {
+    old.keys()
+        .chain(new.keys().filter(|name| !old.contains_key(*name)))
+        .filter(move |name| old.get(*name) != new.get(*name))
+}
+
+/// Commands adding new checks, restarting changed ones and removing the
+/// ones that are gone. Unchanged checks are left alone.
+pub fn diff(old: &HashMap<String, Api>, new: &HashMap<String, Api>) -> Vec<ControlCommand> {
+    let mut commands: Vec<ControlCommand> = old
+        .keys()
+        .filter(|key| !new.contains_key(*key))
+        .map(|key| ControlCommand::Delete(key.to_string()))
+        .collect();
+
+    for (key, api) in new {
+        if old.get(key) != Some(api) {
+            commands.push(ControlCommand::Put(key.to_string(), Box::new(api.clone())));
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> ApisConfig {
+        serde_yml::from_str(yaml).unwrap()
+    }
+
+    fn check(name: &str, interval: u64) -> String {
+        format!(
+            "  {name}:
+    name: {name}
+    request: {{ url: 'http://localhost/{name}', method: GET }}
+    expected_status: 200
+    interval: {interval}
+    system_notify: false
+"
+        )
+    }
+
+    fn step(name: &str, path: &str) -> String {
+        format!("  {name}:\n    request: {{ url: 'http://localhost/{path}', method: POST }}\n")
+    }
+
+    #[test]
+    fn diff_only_touches_changed_checks() {
+        let old = config(&format!(
+            "requests:\n{}{}{}",
+            check("a", 10),
+            check("b", 10),
+            check("c", 10)
+        ));
+        let new = config(&format!(
+            "requests:\n{}{}{}",
+            check("a", 10),
+            check("b", 20),
+            check("d", 10)
+        ));
+
+        let mut commands: Vec<String> = diff(&old.requests, &new.requests)
+            .into_iter()
+            .map(|command| match command {
+                ControlCommand::Put(key, api) => format!("put {key} {}", api.interval),
+                ControlCommand::Delete(key) => format!("delete {key}"),
+                command => panic!("unexpected {command:?}"),
+            })
+            .collect();
+        commands.sort();
+        assert_eq!(commands, ["delete c", "put b 20", "put d 10"]);
+        assert!(diff(&new.requests, &new.requests).is_empty());
+    }
+
+    #[test]
+    fn changed_steps_lists_added_changed_and_removed() {
+        let old = config(&format!(
+            "requests: {{}}\nsteps:\n{}{}{}",
+            step("login", "login"),
+            step("token", "token"),
+            step("same", "same")
+        ));
+        let new = config(&format!(
+            "requests: {{}}\nsteps:\n{}{}{}",
+            step("login", "v2/login"),
+            step("same", "same"),
+            step("extra", "extra")
+        ));
+
+        let mut names: Vec<&String> = changed_steps(&old.steps, &new.steps).collect();
+        names.sort();
+        assert_eq!(names, ["extra", "login", "token"]);
+        assert_eq!(changed_steps(&new.steps, &new.steps).count(), 0);
+    }
+}
@@ -1,138 +1,308 @@
-use reqwest::{header::HeaderMap, header::HeaderName, Client, Method, Response, Url};
-use serde_json::Value;
-use std::collections::HashMap;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE, COOKIE, LOCATION,
+    PROXY_AUTHORIZATION, WWW_AUTHENTICATE,
+};
+use reqwest::{Client, Method, StatusCode, Url};
+use serde_json::{json, Map, Value};
+use std::io;
+use std::time::{Duration, Instant};
 
-use crate::utils::notify::send_notify;
-use crate::utils::yarn::{ConfigMethod, Depends, ReqHash, Request};
-
-use crate::Value::Null;
+use crate::auth;
+use crate::connection::{self, Exchange, Phases};
+use crate::depends_cache::DependsCache;
+use crate::interpolate::Secrets;
+use crate::template::{render, render_request};
+use crate::utils::yarn::{Depends, Request, Step};
 
 // pub mod requests {
 
-pub async fn request_api(
-    request: &Request,
-    headers: HeaderMap,
-    body: &Option<ReqHash>,
-) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
-    let client = Client::builder().build()?;
-
-    // Missing all methods
-    let method: &'static [u8] = if request.method == ConfigMethod::POST {
-        b"POST"
-    } else {
-        b"GET"
-    };
+/// Requests are sent over their own connection so the DNS, connect and TLS
+/// phases can be timed; reqwest does not expose them. Through a proxy they
+/// go through reqwest, and only `ttfb` and `total` are measured.
+const MAX_REDIRECTS: usize = 10;
 
-    // TODO
-    let resp: Response;
-
-    resp = match body {
-        Some(req_body) => {
-            client
-                .request(Method::from_bytes(method)?, Url::parse(&request.url)?)
-                .headers(headers)
-                .json(&req_body)
-                .send()
-                .await?
-        }
-        None => {
-            client
-                .request(Method::from_bytes(method)?, Url::parse(&request.url)?)
-                .headers(headers)
-                .send()
-                .await?
-        }
-    };
+/// Duration of each phase of a request. Phases are those of the connection
+/// the response came from, and are `None` when they did not happen (DNS for
+/// an IP address, TLS for plain HTTP) or through a proxy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+    pub ttfb: Duration,
+    pub total: Duration,
+}
 
-    Ok(resp)
+#[derive(Debug)]
+pub struct ApiResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+    pub timings: Timings,
 }
 
-fn get_field<'a>(value: &'a Value, fields: Vec<&str>, size: usize, start: usize) -> &'a Value {
-    let next_value = &value[fields[start]];
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Send over connections of our own, following redirects the way reqwest
+/// does by default.
+async fn send_direct(
+    mut method: Method,
+    mut url: Url,
+    mut headers: HeaderMap,
+    mut body: Option<Vec<u8>>,
+) -> Result<Exchange, BoxError> {
+    for _ in 0..=MAX_REDIRECTS {
+        let exchange =
+            connection::send(method.clone(), &url, headers.clone(), body.clone()).await?;
+        let location = exchange
+            .headers
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| url.join(location).ok());
+
+        let next = match (exchange.status, location) {
+            (
+                StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER,
+                Some(next),
+            ) => {
+                if method != Method::HEAD {
+                    method = Method::GET;
+                }
+                body = None;
+                headers.remove(CONTENT_TYPE);
+                next
+            }
+            (StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT, Some(next)) => next,
+            _ => return Ok(exchange),
+        };
 
-    if start == (size - 1) {
-        return next_value;
+        let same_origin = next.scheme() == url.scheme()
+            && next.host_str() == url.host_str()
+            && next.port_or_known_default() == url.port_or_known_default();
+        if !same_origin {
+            for name in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE] {
+                headers.remove(name);
+            }
+        }
+        url = next;
     }
+    Err(format!("too many redirects (more than {MAX_REDIRECTS})").into())
+}
+
+/// Send through the proxy reqwest picks from the environment.
+async fn send_proxied(
+    method: Method,
+    url: Url,
+    headers: HeaderMap,
+    body: Option<Vec<u8>>,
+) -> Result<Exchange, BoxError> {
+    let mut builder = Client::new().request(method, url).headers(headers);
+    if let Some(body) = body {
+        builder = builder.body(body);
+    }
+
+    let started = Instant::now();
+    let response = builder.send().await?;
+    let ttfb = started.elapsed();
 
-    return get_field(next_value, fields, size, start + 1);
+    Ok(Exchange {
+        status: response.status(),
+        headers: response.headers().clone(),
+        body: response.bytes().await?,
+        phases: Phases {
+            ttfb,
+            ..Phases::default()
+        },
+    })
 }
 
-pub async fn get_depends_result(
-    depends: &Depends,
-    system_notify: bool,
-) -> HashMap<String, Vec<ReqHash>> {
-    let mut headers_map = HeaderMap::new();
-
-    for header in &depends.request.headers {
-        for (key, value) in header {
-            headers_map.insert(
-                HeaderName::from_lowercase(key.as_bytes()).unwrap(),
-                value.parse().unwrap(),
-            );
+/// Headers of `request`, or a message naming the invalid one.
+fn header_map(request: &Request) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in request.headers.iter().flatten() {
+        let header_name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("invalid header name {name:?}"))?;
+        let header_value =
+            HeaderValue::from_str(value).map_err(|_| format!("invalid value for header {name}"))?;
+        headers.insert(header_name, header_value);
+    }
+    Ok(headers)
+}
+
+/// Send `request` once. Returns whether a cached OAuth2 token was used.
+async fn send(
+    request: &Request,
+    timeout: Duration,
+    cache: &DependsCache,
+    refresh: bool,
+    secrets: &mut Secrets,
+) -> Result<(ApiResponse, bool), BoxError> {
+    let method = Method::from_bytes(request.method.as_str().as_bytes())?;
+    let mut url = Url::parse(&request.url)?;
+    if let Some(query) = &request.query {
+        let mut query: Vec<(&String, &String)> = query.iter().collect();
+        query.sort();
+        url.query_pairs_mut().extend_pairs(query);
+    }
+    let mut headers = header_map(request)?;
+    let cached_token = match &request.auth {
+        Some(auth) => {
+            auth::apply(
+                auth,
+                &mut url,
+                &mut headers,
+                timeout,
+                cache,
+                refresh,
+                secrets,
+            )
+            .await?
         }
+        None => false,
+    };
+
+    let body = request.body.as_ref().map(serde_json::to_vec).transpose()?;
+    if body.is_some() {
+        headers
+            .entry(CONTENT_TYPE)
+            .or_insert(HeaderValue::from_static("application/json"));
     }
-    let response = request_api(&depends.request, headers_map, &depends.request.body).await;
+    headers
+        .entry(ACCEPT)
+        .or_insert(HeaderValue::from_static("*/*"));
 
-    return match response {
-        Ok(r) => {
-            let body = r.text().await;
+    let started = Instant::now();
+    let exchange = if connection::proxied(&url) {
+        tokio::time::timeout(timeout, send_proxied(method, url, headers, body)).await
+    } else {
+        tokio::time::timeout(timeout, send_direct(method, url, headers, body)).await
+    };
+    let exchange = exchange.map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("request timed out after {}s", timeout.as_secs()),
+        )
+    })??;
 
-            let result: Value = match body {
-                Ok(r) => {
-                    println!("{:?}", r);
-                    let body_json = serde_json::from_str(&r).unwrap();
-                    body_json
-                }
-                Err(e) => panic!("TODO: {:?}", e),
-            };
+    let phases = exchange.phases;
+    let timings = Timings {
+        dns: phases.dns,
+        connect: phases.connect,
+        tls: phases.tls,
+        ttfb: phases.ttfb,
+        total: started.elapsed(),
+    };
+    let response = ApiResponse {
+        status: exchange.status,
+        headers: exchange.headers,
+        body: String::from_utf8_lossy(&exchange.body).into_owned(),
+        timings,
+    };
+    Ok((response, cached_token))
+}
 
-            let mut depends_headers: Vec<ReqHash> = Vec::new();
+/// Send `request` with its `auth` applied, adding the credentials used to
+/// `secrets`. A `401` answered to a cached OAuth2 token is retried once
+/// with a new token.
+pub async fn request_api(
+    request: &Request,
+    timeout: Duration,
+    cache: &DependsCache,
+    secrets: &mut Secrets,
+) -> Result<ApiResponse, BoxError> {
+    let (response, cached_token) = send(request, timeout, cache, false, secrets).await?;
+    if response.status == StatusCode::UNAUTHORIZED && cached_token {
+        return Ok(send(request, timeout, cache, true, secrets).await?.0);
+    }
+    Ok(response)
+}
 
-            for header_field in &depends.header_fields {
-                let fields: Vec<_> = header_field.split('.').collect();
-                let size = fields.len();
-                let result_field = get_field(&result, fields, size, 0);
+/// Run one `depends_on` step, its templates rendered with the variables of
+/// the previous steps, and return its JSON response and whether it came
+/// from `cache`. `refresh` skips the cached response.
+pub async fn get_depends_result(
+    depends: &Depends,
+    variables: &Value,
+    timeout: Duration,
+    cache: &DependsCache,
+    refresh: bool,
+    secrets: &mut Secrets,
+) -> Result<(Value, bool), String> {
+    let request = render_request(&depends.request, variables)
+        .map_err(|e| format!("depends_on {}: template error in {e}", depends.name))?;
 
-                let mut fields_values = HashMap::new();
-                if result_field != &Null {
-                    fields_values.insert(header_field.to_string(), result_field.to_string());
-                }
+    if !refresh {
+        if let Some(response) = cache.get(&request) {
+            return Ok((response, true));
+        }
+    }
 
-                depends_headers.push(fields_values);
-            }
+    let response = request_api(&request, timeout, cache, secrets)
+        .await
+        .map_err(|e| format!("depends_on {} failed: {e}", depends.name))?;
 
-            let mut depends_body: Vec<ReqHash> = Vec::new();
+    if !response.status.is_success() {
+        return Err(format!(
+            "depends_on {} failed with status {}",
+            depends.name, response.status
+        ));
+    }
 
-            for body_field in &depends.body_fields {
-                let fields: Vec<_> = body_field.split('.').collect();
-                let size = fields.len();
-                let result_field = get_field(&result, fields, size, 0);
+    let response = serde_json::from_str(&response.body)
+        .map_err(|e| format!("depends_on {}: response is not JSON ({e})", depends.name))?;
+    cache.insert(depends, &request, &response);
+    Ok((response, false))
+}
 
-                let mut fields_values = HashMap::new();
-                if result_field != &Null {
-                    fields_values.insert(
-                        body_field.to_string(),
-                        serde_json::to_string(result_field).unwrap(),
-                    );
-                }
+/// Add `new` to `variables`, replacing fields of the same name. A response
+/// that is not an object replaces them all.
+fn merge(variables: &mut Value, new: Value) {
+    match (variables, new) {
+        (Value::Object(variables), Value::Object(new)) => variables.extend(new),
+        (variables, new) => *variables = new,
+    }
+}
 
-                depends_body.push(fields_values);
-            }
+/// Run the steps in order and return the variables they set, and whether
+/// any response came from `cache`.
+pub async fn run_steps(
+    steps: &[Step],
+    timeout: Duration,
+    cache: &DependsCache,
+    refresh: bool,
+    secrets: &mut Secrets,
+) -> Result<(Value, bool), String> {
+    let mut variables = Value::Object(Map::new());
+    let mut any_cached = false;
 
-            let mut depends_results: HashMap<String, Vec<ReqHash>> = HashMap::new();
-            depends_results.insert("depends_body".to_string(), depends_body);
-            depends_results.insert("depends_headers".to_string(), depends_headers);
+    for step in steps {
+        let depends = match step {
+            Step::Request(depends) => depends,
+            Step::Shared(name) => return Err(format!("unknown step {name}")),
+        };
 
-            depends_results
+        let (response, cached) =
+            get_depends_result(depends, &variables, timeout, cache, refresh, secrets).await?;
+        any_cached |= cached;
+        if depends.extract.is_empty() {
+            merge(&mut variables, response);
+            continue;
         }
-        Err(_) => {
-            if system_notify == true {
-                let msg = format!("Error to request depends");
 
-                send_notify(depends.name.as_str(), "dialog-error", &msg).unwrap();
-            }
-            HashMap::new()
+        // Extracted templates see the earlier variables and the response.
+        let mut scope = variables.clone();
+        merge(&mut scope, response);
+        for (name, template) in &depends.extract {
+            let value = render(template, &scope).map_err(|e| {
+                format!(
+                    "depends_on {}: template error in extract.{name}: {e}",
+                    depends.name
+                )
+            })?;
+            merge(&mut variables, json!({ name: value }));
         }
-    };
+    }
+
+    Ok((variables, any_cached))
 }
 // }
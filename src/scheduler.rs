@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task;
+
+use crate::app::ListRequests;
+use crate::check::verify_api;
+use crate::depends_cache::DependsCache;
+use crate::utils::yarn::{Api, ApisConfig, Depends};
+
+/// Runs every check on its own interval in a background task and collects
+/// the latest result of each one.
+pub struct Scheduler {
+    handles: HashMap<usize, task::JoinHandle<()>>,
+    triggers: HashMap<usize, Arc<Notify>>,
+    results: Arc<Mutex<Vec<ListRequests>>>,
+    running: Arc<AtomicBool>,
+    /// `depends_on` responses shared by every check.
+    cache: DependsCache,
+}
+
+impl Scheduler {
+    pub fn new(initial: Vec<ListRequests>) -> Scheduler {
+        Scheduler {
+            handles: HashMap::new(),
+            triggers: HashMap::new(),
+            results: Arc::new(Mutex::new(initial)),
+            running: Arc::new(AtomicBool::new(true)),
+            cache: DependsCache::default(),
+        }
+    }
+
+    /// Start a task for every check that is not paused and not already
+    /// waiting or running.
+    pub fn tick(&mut self, configs: &ApisConfig) {
+        self.handles.retain(|_, handle| !handle.is_finished());
+
+        let rows: Vec<(usize, String, bool)> = self
+            .results
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|row| (row.id, row.key.clone(), row.paused))
+            .collect();
+
+        for (id, key, paused) in rows {
+            if paused || self.handles.contains_key(&id) {
+                continue;
+            }
+            if let Some(api_config) = configs.requests.get(&key) {
+                let steps = configs.steps.clone();
+                self.spawn(id, api_config.clone(), steps, api_config.interval);
+            }
+        }
+    }
+
+    fn spawn(
+        &mut self,
+        id: usize,
+        api_config: Api,
+        steps: HashMap<String, Depends>,
+        countdown: u64,
+    ) {
+        let results = Arc::clone(&self.results);
+        let running = self.running.clone();
+        let cache = self.cache.clone();
+        let trigger = self.triggers.entry(id).or_default().clone();
+
+        let handle = task::spawn(async move {
+            let mut counter = countdown;
+            while running.load(Ordering::SeqCst) {
+                if counter > 0 {
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(1)) => counter -= 1,
+                        _ = trigger.notified() => counter = 0,
+                    }
+                }
+
+                if counter < 1 {
+                    let muted = results
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .any(|r| r.id == id && r.is_muted());
+                    let mut api_config = api_config.clone();
+                    if muted {
+                        api_config.system_notify = false;
+                    }
+
+                    let status_api = verify_api(&api_config, &steps, &cache).await;
+
+                    let mut results = results.lock().unwrap();
+                    if let Some(status) = results.iter_mut().find(|r| r.id == id) {
+                        status.data = status_api;
+                        status.interval = api_config.interval;
+                    }
+                    break;
+                }
+
+                let mut results = results.lock().unwrap();
+                if let Some(status) = results.iter_mut().find(|r| r.id == id) {
+                    status.interval = counter;
+                }
+            }
+        });
+        self.handles.insert(id, handle);
+    }
+
+    /// Add a check, or replace the row of an existing one and restart it so
+    /// the next run uses its new settings. Pausing and muting are kept.
+    pub fn upsert(&mut self, mut row: ListRequests) {
+        if let Some(handle) = self.handles.remove(&row.id) {
+            handle.abort();
+        }
+
+        let mut results = self.results.lock().unwrap();
+        match results.iter_mut().find(|r| r.id == row.id) {
+            Some(existing) => {
+                row.paused = existing.paused;
+                row.muted_until = existing.muted_until;
+                *existing = row;
+            }
+            None => results.push(row),
+        }
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        if let Some(handle) = self.handles.remove(&id) {
+            handle.abort();
+        }
+        self.triggers.remove(&id);
+        self.results.lock().unwrap().retain(|r| r.id != id);
+    }
+
+    /// Pause or resume a check. A paused check keeps its last result and is
+    /// not scheduled until resumed.
+    pub fn set_paused(&mut self, id: usize, paused: bool) {
+        if paused {
+            if let Some(handle) = self.handles.remove(&id) {
+                handle.abort();
+            }
+        }
+
+        if let Some(row) = self.results.lock().unwrap().iter_mut().find(|r| r.id == id) {
+            row.paused = paused;
+        }
+    }
+
+    /// Stop system notifications of a check until `until`, or send them
+    /// again when `None`.
+    pub fn set_muted(&mut self, id: usize, until: Option<Instant>) {
+        if let Some(row) = self.results.lock().unwrap().iter_mut().find(|r| r.id == id) {
+            row.muted_until = until;
+        }
+    }
+
+    /// Run a check right away instead of waiting for its countdown. A paused
+    /// check runs once and stays paused.
+    pub fn run_now(&mut self, id: usize, api_config: &Api, steps: &HashMap<String, Depends>) {
+        match self.handles.get(&id) {
+            Some(handle) if !handle.is_finished() => {
+                self.triggers.entry(id).or_default().notify_waiters();
+            }
+            _ => self.spawn(id, api_config.clone(), steps.clone(), 0),
+        }
+    }
+
+    /// Snapshot of the latest result of every check.
+    pub fn results(&self) -> Vec<ListRequests> {
+        self.results.lock().unwrap().clone()
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,17 @@
+use axum::Router;
+use std::io;
+use tokio::net::TcpListener;
+
+/// Bind `listen` and serve `router` in a background task. Binding errors are
+/// returned right away so a bad address is reported at startup.
+pub async fn spawn(listen: &str, router: Router) -> io::Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            tracing::error!("http server stopped: {e}");
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use axum::response::Html;
+use axum::routing::get;
+use axum::Router;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::app::ListRequests;
+use crate::check::{CheckResult, CheckState};
+use crate::history::History;
+use crate::output::escape_xml;
+use crate::utils::yarn::StatusPageConfig;
+
+/// Number of past results drawn as bars for each check.
+const BARS: usize = 60;
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// What the status page shows for one check.
+#[derive(Debug, Clone)]
+pub struct CheckStatus {
+    pub group: String,
+    pub name: String,
+    pub result: CheckResult,
+    pub uptime_day: Option<f64>,
+    pub uptime_all: Option<f64>,
+    /// Oldest first, at most `BARS` entries.
+    pub bars: Vec<CheckResult>,
+}
+
+impl CheckStatus {
+    pub fn new(row: &ListRequests, group: Option<&str>, history: Option<&History>) -> CheckStatus {
+        let bars = history
+            .map(|h| {
+                let mut bars: Vec<CheckResult> = h.iter().rev().take(BARS).cloned().collect();
+                bars.reverse();
+                bars
+            })
+            .unwrap_or_default();
+
+        CheckStatus {
+            group: group.unwrap_or("Checks").to_string(),
+            name: row.data.name.to_string(),
+            result: row.data.result.clone(),
+            uptime_day: history.and_then(|h| h.uptime(Some(DAY))),
+            uptime_all: history.and_then(|h| h.uptime(None)),
+            bars,
+        }
+    }
+}
+
+fn state_class(state: CheckState) -> &'static str {
+    match state {
+        CheckState::Up => "up",
+        CheckState::Degraded => "degraded",
+        CheckState::Down => "down",
+        CheckState::Unknown => "unknown",
+        CheckState::Pending => "pending",
+    }
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; max-width: 960px; margin: 2em auto; color: #222; }
+h1 { margin-bottom: 0.2em; }
+.summary { padding: 1em; border-radius: 6px; color: #fff; margin: 1em 0; }
+.check { border-bottom: 1px solid #eee; padding: 0.8em 0; }
+.head { display: flex; justify-content: space-between; }
+.badge { font-weight: bold; }
+.bars { display: flex; gap: 2px; margin: 0.4em 0; height: 24px; }
+.bar { flex: 1; border-radius: 2px; }
+.meta, .error { font-size: 0.85em; color: #666; }
+.error { color: #c0392b; }
+.up { background: #2ecc71; } .badge.up { background: none; color: #27ae60; }
+.degraded { background: #f1c40f; } .badge.degraded { background: none; color: #d4ac0d; }
+.down { background: #e74c3c; } .badge.down { background: none; color: #c0392b; }
+.unknown { background: #9b59b6; } .badge.unknown { background: none; color: #8e44ad; }
+.pending { background: #ddd; } .badge.pending { background: none; color: #999; }
+";
+
+fn format_uptime(uptime: Option<f64>) -> String {
+    uptime
+        .map(|uptime| format!("{uptime:.2}%"))
+        .unwrap_or_else(|| String::from("-"))
+}
+
+pub fn render(config: &StatusPageConfig, checks: &[CheckStatus]) -> String {
+    let mut groups: BTreeMap<&str, Vec<&CheckStatus>> = BTreeMap::new();
+    for check in checks {
+        groups.entry(&check.group).or_default().push(check);
+    }
+
+    let failing = checks.iter().filter(|c| c.result.is_failure()).count();
+    let (summary_class, summary) = match failing {
+        0 => ("up", String::from("All systems operational")),
+        1 => ("down", String::from("1 check is failing")),
+        n => ("down", format!("{n} checks are failing")),
+    };
+
+    let title = escape_xml(&config.title);
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"{}\">\
+         <title>{title}</title><style>{STYLE}</style></head><body>\
+         <h1>{title}</h1><div class=\"meta\">Updated {}</div>\
+         <div class=\"summary {summary_class}\">{summary}</div>",
+        config.refresh,
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+    );
+
+    for (group, checks) in groups {
+        let _ = write!(html, "<h2>{}</h2>", escape_xml(group));
+
+        for check in checks {
+            let result = &check.result;
+            let class = state_class(result.state);
+            let _ = write!(
+                html,
+                "<div class=\"check\"><div class=\"head\"><span>{}</span>\
+                 <span class=\"badge {class}\">{}</span></div><div class=\"bars\">",
+                escape_xml(&check.name),
+                result.state,
+            );
+
+            for _ in check.bars.len()..BARS {
+                html.push_str("<div class=\"bar pending\"></div>");
+            }
+            for bar in &check.bars {
+                let time = bar
+                    .checked_at
+                    .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default();
+                let _ = write!(
+                    html,
+                    "<div class=\"bar {}\" title=\"{time} {}\"></div>",
+                    state_class(bar.state),
+                    bar.state
+                );
+            }
+
+            let latency = result
+                .latency()
+                .map(|l| format!("{}ms", l.as_millis()))
+                .unwrap_or_else(|| String::from("-"));
+            let _ = write!(
+                html,
+                "</div><div class=\"meta\">Uptime 24h {} &middot; all {} &middot; latency {latency}</div>",
+                format_uptime(check.uptime_day),
+                format_uptime(check.uptime_all),
+            );
+            if let Some(error) = &result.error_message {
+                let _ = write!(html, "<div class=\"error\">{}</div>", escape_xml(error));
+            }
+            html.push_str("</div>");
+        }
+    }
+
+    html.push_str("</body></html>\n");
+    html
+}
+
+/// Router serving the page on `/`.
+pub fn router(config: StatusPageConfig, checks: Arc<Mutex<Vec<CheckStatus>>>) -> Router {
+    Router::new().route(
+        "/",
+        get(move || async move { Html(render(&config, &checks.lock().unwrap())) }),
+    )
+}
@@ -0,0 +1,307 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::app::ApiInformation;
+use crate::check::CheckResult;
+use crate::request::Timings;
+use crate::utils::yarn::{StorageBackend, StorageConfig};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS check_results (
+    id INTEGER PRIMARY KEY,
+    check_key TEXT NOT NULL,
+    name TEXT NOT NULL,
+    url TEXT NOT NULL,
+    method TEXT NOT NULL,
+    state TEXT NOT NULL,
+    http_status INTEGER,
+    error_kind TEXT,
+    error_message TEXT,
+    assertion_failures TEXT NOT NULL,
+    dns_ms REAL,
+    connect_ms REAL,
+    tls_ms REAL,
+    ttfb_ms REAL,
+    total_ms REAL,
+    attempts INTEGER NOT NULL,
+    checked_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS check_results_key_time ON check_results (check_key, checked_at);
+";
+
+/// One persisted check result, as written to SQLite or a JSONL line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredResult {
+    pub check_key: String,
+    pub name: String,
+    pub url: String,
+    pub method: String,
+    pub state: String,
+    pub http_status: Option<u16>,
+    pub error_kind: Option<String>,
+    pub error_message: Option<String>,
+    pub assertion_failures: Vec<String>,
+    pub dns_ms: Option<f64>,
+    pub connect_ms: Option<f64>,
+    pub tls_ms: Option<f64>,
+    pub ttfb_ms: Option<f64>,
+    pub total_ms: Option<f64>,
+    pub attempts: u32,
+    pub checked_at: String,
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn from_ms(ms: f64) -> Duration {
+    Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+impl StoredResult {
+    pub fn new(check_key: &str, info: &ApiInformation) -> StoredResult {
+        let result = &info.result;
+        let timings = result.timings.as_ref();
+
+        StoredResult {
+            check_key: check_key.to_string(),
+            name: info.name.to_string(),
+            url: info.url.to_string(),
+            method: info.method.to_string(),
+            state: result.state.to_string(),
+            http_status: result.http_status,
+            error_kind: result.error_kind.map(|kind| kind.to_string()),
+            error_message: result.error_message.clone(),
+            assertion_failures: result.assertion_failures.clone(),
+            dns_ms: timings.and_then(|t| t.dns).map(to_ms),
+            connect_ms: timings.and_then(|t| t.connect).map(to_ms),
+            tls_ms: timings.and_then(|t| t.tls).map(to_ms),
+            ttfb_ms: timings.map(|t| to_ms(t.ttfb)),
+            total_ms: timings.map(|t| to_ms(t.total)),
+            attempts: result.attempts,
+            checked_at: result
+                .checked_at
+                .as_ref()
+                .map(format_time)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn into_result(self) -> Result<CheckResult, Box<dyn Error>> {
+        let timings = self.total_ms.map(|total| Timings {
+            dns: self.dns_ms.map(from_ms),
+            connect: self.connect_ms.map(from_ms),
+            tls: self.tls_ms.map(from_ms),
+            ttfb: from_ms(self.ttfb_ms.unwrap_or(total)),
+            total: from_ms(total),
+        });
+
+        Ok(CheckResult {
+            state: self.state.parse()?,
+            http_status: self.http_status,
+            error_kind: self.error_kind.map(|kind| kind.parse()).transpose()?,
+            error_message: self.error_message,
+            assertion_failures: self.assertion_failures,
+            timings,
+            attempts: self.attempts,
+            checked_at: Some(DateTime::parse_from_rfc3339(&self.checked_at)?.with_timezone(&Utc)),
+            response: None,
+        })
+    }
+}
+
+pub enum Storage {
+    Sqlite(Connection),
+    Jsonl(PathBuf),
+}
+
+impl Storage {
+    pub fn open(config: &StorageConfig) -> Result<Storage, Box<dyn Error>> {
+        match config.backend {
+            StorageBackend::Sqlite => {
+                let connection = Connection::open(&config.path)?;
+                connection.execute_batch(SCHEMA)?;
+                Ok(Storage::Sqlite(connection))
+            }
+            StorageBackend::Jsonl => Ok(Storage::Jsonl(PathBuf::from(&config.path))),
+        }
+    }
+
+    pub fn save(&mut self, record: &StoredResult) -> Result<(), Box<dyn Error>> {
+        match self {
+            Storage::Sqlite(connection) => {
+                connection.execute(
+                    "INSERT INTO check_results (
+                        check_key, name, url, method, state, http_status, error_kind,
+                        error_message, assertion_failures, dns_ms, connect_ms, tls_ms,
+                        ttfb_ms, total_ms, attempts, checked_at
+                    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+                    params![
+                        record.check_key,
+                        record.name,
+                        record.url,
+                        record.method,
+                        record.state,
+                        record.http_status,
+                        record.error_kind,
+                        record.error_message,
+                        serde_json::to_string(&record.assertion_failures)?,
+                        record.dns_ms,
+                        record.connect_ms,
+                        record.tls_ms,
+                        record.ttfb_ms,
+                        record.total_ms,
+                        record.attempts,
+                        record.checked_at,
+                    ],
+                )?;
+            }
+            Storage::Jsonl(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", serde_json::to_string(record)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Results of one check newer than `since`, oldest first, at most `limit`.
+    pub fn load(
+        &self,
+        check_key: &str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<CheckResult>, Box<dyn Error>> {
+        let since = format_time(&since);
+
+        let records = match self {
+            Storage::Sqlite(connection) => {
+                let mut statement = connection.prepare(
+                    "SELECT check_key, name, url, method, state, http_status, error_kind,
+                        error_message, assertion_failures, dns_ms, connect_ms, tls_ms,
+                        ttfb_ms, total_ms, attempts, checked_at
+                    FROM check_results
+                    WHERE check_key = ?1 AND checked_at >= ?2
+                    ORDER BY checked_at DESC
+                    LIMIT ?3",
+                )?;
+                let rows = statement.query_map(params![check_key, since, limit], |row| {
+                    let failures: String = row.get(8)?;
+                    Ok(StoredResult {
+                        check_key: row.get(0)?,
+                        name: row.get(1)?,
+                        url: row.get(2)?,
+                        method: row.get(3)?,
+                        state: row.get(4)?,
+                        http_status: row.get(5)?,
+                        error_kind: row.get(6)?,
+                        error_message: row.get(7)?,
+                        assertion_failures: serde_json::from_str(&failures).unwrap_or_default(),
+                        dns_ms: row.get(9)?,
+                        connect_ms: row.get(10)?,
+                        tls_ms: row.get(11)?,
+                        ttfb_ms: row.get(12)?,
+                        total_ms: row.get(13)?,
+                        attempts: row.get(14)?,
+                        checked_at: row.get(15)?,
+                    })
+                })?;
+                let mut records = rows.collect::<Result<Vec<_>, _>>()?;
+                records.reverse();
+                records
+            }
+            Storage::Jsonl(path) => {
+                let content = match fs::read_to_string(path) {
+                    Ok(content) => content,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                    Err(e) => return Err(e.into()),
+                };
+                let mut records: Vec<StoredResult> = content
+                    .lines()
+                    .filter_map(|line| serde_json::from_str::<StoredResult>(line).ok())
+                    .filter(|record| record.check_key == check_key && record.checked_at >= since)
+                    .collect();
+                let skip = records.len().saturating_sub(limit);
+                records.drain(..skip);
+                records
+            }
+        };
+
+        records.into_iter().map(StoredResult::into_result).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::{CheckState, ErrorKind};
+    use chrono::TimeZone;
+
+    fn info() -> ApiInformation {
+        ApiInformation {
+            name: String::from("Orders"),
+            url: String::from("https://example.com/orders"),
+            method: String::from("GET"),
+            result: CheckResult {
+                state: CheckState::Down,
+                http_status: Some(503),
+                error_kind: Some(ErrorKind::Assertion),
+                error_message: Some(String::from("1 assertion failed")),
+                assertion_failures: vec![String::from("status: expected \"ok\"")],
+                timings: Some(Timings {
+                    dns: None,
+                    connect: Some(Duration::from_millis(3)),
+                    tls: Some(Duration::from_millis(12)),
+                    ttfb: Duration::from_millis(40),
+                    total: Duration::from_millis(55),
+                }),
+                attempts: 2,
+                checked_at: Some(Utc.timestamp_millis_opt(1_700_000_000_123).unwrap()),
+                response: None,
+            },
+        }
+    }
+
+    #[test]
+    fn stored_result_round_trips() {
+        let info = info();
+        let record = StoredResult::new("orders", &info);
+        assert_eq!(record.tls_ms, Some(12.0));
+        assert_eq!(record.checked_at, "2023-11-14T22:13:20.123Z");
+        assert_eq!(record.into_result().unwrap(), info.result);
+
+        let pending = ApiInformation {
+            result: CheckResult::pending(),
+            ..info
+        };
+        assert!(StoredResult::new("orders", &pending).into_result().is_err());
+    }
+
+    #[test]
+    fn sqlite_loads_what_was_saved() {
+        let mut storage = Storage::open(&StorageConfig {
+            backend: StorageBackend::Sqlite,
+            path: String::from(":memory:"),
+            load_hours: 24,
+        })
+        .unwrap();
+        let info = info();
+        storage.save(&StoredResult::new("orders", &info)).unwrap();
+
+        let since = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        assert_eq!(
+            storage.load("orders", since, 10).unwrap(),
+            vec![info.result]
+        );
+        assert!(storage.load("other", since, 10).unwrap().is_empty());
+    }
+}
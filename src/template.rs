@@ -0,0 +1,379 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::format::{Item, StrftimeItems};
+use chrono::Utc;
+use rand::Rng;
+use serde_json::Value;
+
+use crate::assertion::json_field;
+use crate::utils::yarn::{ReqHash, Request};
+
+/// Value of a `{{ ... }}` expression before filters are applied.
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    /// Dotted path into the variables, `[n]` or numeric segments index arrays.
+    Variable(String),
+    Literal(String),
+    /// Current UTC time, RFC 3339 or formatted with a chrono format string.
+    Now(Option<String>),
+    Uuid,
+    RandomInt(i64, i64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    /// Used when the value is missing or null.
+    Default(String),
+    Base64,
+    Urlencode,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Expression(Source, Vec<Filter>),
+}
+
+/// A string with `{{ expression | filter }}` blocks, e.g.
+/// `Bearer {{ auth.token }}` or `{{ items[0].id | default("none") }}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+/// `items[0].id` written as `items.0.id`.
+pub fn variable_path(path: &str) -> String {
+    path.replace('[', ".").replace(']', "")
+}
+
+/// Split on `separator` outside of double quoted strings.
+fn split_outside_quotes(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c == separator && !in_quotes => {
+                parts.push(&text[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Name and arguments of `name(arg, "arg")`, or just the name.
+fn parse_call(text: &str) -> Result<(&str, Vec<String>), String> {
+    let Some(open) = text.find('(') else {
+        return Ok((text, Vec::new()));
+    };
+    let args = text[open + 1..]
+        .strip_suffix(')')
+        .ok_or_else(|| format!("missing `)` in `{text}`"))?;
+
+    let args = if args.trim().is_empty() {
+        Vec::new()
+    } else {
+        split_outside_quotes(args, ',')
+            .into_iter()
+            .map(|arg| {
+                let arg = arg.trim();
+                arg.strip_prefix('"')
+                    .and_then(|arg| arg.strip_suffix('"'))
+                    .unwrap_or(arg)
+                    .to_string()
+            })
+            .collect()
+    };
+    Ok((text[..open].trim(), args))
+}
+
+fn parse_source(text: &str) -> Result<Source, String> {
+    if let Some(literal) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return Ok(Source::Literal(literal.to_string()));
+    }
+
+    let (name, args) = parse_call(text)?;
+    match (name, args.as_slice()) {
+        ("now", []) => Ok(Source::Now(None)),
+        ("now", [format]) => {
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(format!("now: invalid format `{format}`"));
+            }
+            Ok(Source::Now(Some(format.to_string())))
+        }
+        ("uuid", []) => Ok(Source::Uuid),
+        ("random_int", [min, max]) => {
+            let bound = |value: &str| {
+                value
+                    .parse::<i64>()
+                    .map_err(|_| format!("random_int: {value} is not a number"))
+            };
+            let (min, max) = (bound(min)?, bound(max)?);
+            if min > max {
+                return Err(format!("random_int: {min} is above {max}"));
+            }
+            Ok(Source::RandomInt(min, max))
+        }
+        ("now" | "uuid" | "random_int", _) => Err(format!("wrong arguments for {name}")),
+        (path, []) if !text.contains('(') => {
+            let valid = !path.is_empty()
+                && path
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "_-.[]".contains(c));
+            if !valid {
+                return Err(format!("invalid variable `{path}`"));
+            }
+            Ok(Source::Variable(variable_path(path)))
+        }
+        _ => Err(format!("unknown function {name}")),
+    }
+}
+
+fn parse_filter(text: &str) -> Result<Filter, String> {
+    let (name, args) = parse_call(text)?;
+    match (name, args.as_slice()) {
+        ("default", [value]) => Ok(Filter::Default(value.to_string())),
+        ("base64", []) => Ok(Filter::Base64),
+        ("urlencode", []) => Ok(Filter::Urlencode),
+        ("json", []) => Ok(Filter::Json),
+        ("default" | "base64" | "urlencode" | "json", _) => {
+            Err(format!("wrong arguments for filter {name}"))
+        }
+        _ => Err(format!("unknown filter {name}")),
+    }
+}
+
+fn parse_expression(text: &str) -> Result<Part, String> {
+    let mut pieces = split_outside_quotes(text, '|').into_iter().map(str::trim);
+    let source = parse_source(pieces.next().unwrap_or_default())?;
+    let filters = pieces.map(parse_filter).collect::<Result<_, _>>()?;
+    Ok(Part::Expression(source, filters))
+}
+
+fn urlencode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn uuid() -> String {
+    let mut bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Text of a value: strings as they are, anything else as JSON.
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.to_string(),
+        value => value.to_string(),
+    }
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| String::from("missing `}}`"))?;
+            parts.push(parse_expression(rest[start + 2..start + end].trim())?);
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        Ok(Template { parts })
+    }
+
+    /// Paths of the variables used, as written with dots.
+    pub fn variables(&self) -> Vec<&str> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Expression(Source::Variable(path), filters) => {
+                    let has_default = filters.iter().any(|f| matches!(f, Filter::Default(_)));
+                    (!has_default).then_some(path.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn render(&self, variables: &Value) -> Result<String, String> {
+        let mut output = String::new();
+
+        for part in &self.parts {
+            let (source, filters) = match part {
+                Part::Text(text) => {
+                    output.push_str(text);
+                    continue;
+                }
+                Part::Expression(source, filters) => (source, filters),
+            };
+
+            let mut value = match source {
+                Source::Variable(path) => json_field(variables, path)
+                    .filter(|value| !value.is_null())
+                    .cloned(),
+                Source::Literal(text) => Some(Value::from(text.as_str())),
+                Source::Now(None) => Some(Value::from(Utc::now().to_rfc3339())),
+                Source::Now(Some(format)) => {
+                    Some(Value::from(Utc::now().format(format).to_string()))
+                }
+                Source::Uuid => Some(Value::from(uuid())),
+                Source::RandomInt(min, max) => {
+                    Some(Value::from(rand::thread_rng().gen_range(*min..=*max)))
+                }
+            };
+
+            for filter in filters {
+                value = match (filter, value) {
+                    (Filter::Default(default), None) => Some(Value::from(default.as_str())),
+                    (_, None) => None,
+                    (Filter::Default(_), Some(value)) => Some(value),
+                    (Filter::Base64, Some(value)) => {
+                        Some(Value::from(STANDARD.encode(to_text(&value))))
+                    }
+                    (Filter::Urlencode, Some(value)) => {
+                        Some(Value::from(urlencode(&to_text(&value))))
+                    }
+                    (Filter::Json, Some(value)) => Some(Value::from(value.to_string())),
+                };
+            }
+
+            match (value, source) {
+                (Some(value), _) => output.push_str(&to_text(&value)),
+                (None, Source::Variable(path)) => {
+                    return Err(format!("unresolved variable {path}"));
+                }
+                (None, _) => unreachable!("only variables can be missing"),
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Parse and render `text` in one go.
+pub fn render(text: &str, variables: &Value) -> Result<String, String> {
+    Template::parse(text)?.render(variables)
+}
+
+fn render_pairs(
+    pairs: &Option<ReqHash>,
+    variables: &Value,
+    field: &str,
+) -> Result<Option<ReqHash>, String> {
+    pairs
+        .as_ref()
+        .map(|pairs| {
+            pairs
+                .iter()
+                .map(|(key, value)| {
+                    let error = |e| format!("{field}.{key}: {e}");
+                    Ok((
+                        render(key, variables).map_err(error)?,
+                        render(value, variables).map_err(error)?,
+                    ))
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+/// Copy of `request` with every template of its URL, query, headers, body
+/// and auth rendered. Errors start with the field they come from.
+pub fn render_request(request: &Request, variables: &Value) -> Result<Request, String> {
+    Ok(Request {
+        url: render(&request.url, variables).map_err(|e| format!("url: {e}"))?,
+        query: render_pairs(&request.query, variables, "query")?,
+        headers: render_pairs(&request.headers, variables, "headers")?,
+        method: request.method.clone(),
+        body: render_pairs(&request.body, variables, "body")?,
+        auth: request
+            .auth
+            .as_ref()
+            .map(|auth| {
+                auth.map_text(|field, text| {
+                    render(text, variables).map_err(|e| format!("auth.{field}: {e}"))
+                })
+            })
+            .transpose()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renders_paths_and_filters() {
+        let variables = json!({
+            "auth": { "token": "abc" },
+            "items": [{ "id": 7, "name": "a b&c" }],
+            "empty": null,
+        });
+
+        let rendered = |text| render(text, &variables).unwrap();
+        assert_eq!(rendered("Bearer {{ auth.token }}"), "Bearer abc");
+        assert_eq!(rendered("{{items[0].id}}"), "7");
+        assert_eq!(rendered("{{ items.0.name | urlencode }}"), "a%20b%26c");
+        assert_eq!(rendered("{{ auth.token | base64 }}"), "YWJj");
+        assert_eq!(rendered("{{ auth | json }}"), r#"{"token":"abc"}"#);
+        assert_eq!(rendered(r#"{{ empty | default("none") }}"#), "none");
+        assert_eq!(rendered(r#"{{ missing | default("a|b") }}"#), "a|b");
+        assert_eq!(rendered(r#"{{ "x y" | urlencode }}"#), "x%20y");
+    }
+
+    #[test]
+    fn unresolved_variable_is_an_error() {
+        let variables = json!({ "auth": {} });
+        assert_eq!(
+            render("Bearer {{ auth.token }}", &variables),
+            Err(String::from("unresolved variable auth.token"))
+        );
+        assert_eq!(
+            Template::parse(r#"{{ a }}{{ b | default("x") }}{{ c[1] }}"#)
+                .unwrap()
+                .variables(),
+            vec!["a", "c.1"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(Template::parse("{{ token").is_err());
+        assert!(Template::parse("{{ token | upper }}").is_err());
+        assert!(Template::parse(r#"{{ now("%Q") }}"#).is_err());
+        assert!(Template::parse("{{ random_int(5, 1) }}").is_err());
+        assert!(Template::parse(r#"{{ now("%Y-%m-%d") }}"#).is_ok());
+    }
+}
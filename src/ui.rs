@@ -1,16 +1,21 @@
 use ratatui::{
-    layout::{Alignment, Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style, Stylize},
     symbols::border,
     text::{Line, Text},
     widgets::{
         block::{Position, Title},
-        Block, BorderType, Borders, Cell, Padding, Paragraph, Row, Table,
+        Block, BorderType, Borders, Cell, Clear, Padding, Paragraph, Row, Sparkline, Table, Wrap,
     },
     Frame,
 };
 
-use crate::app::App;
+use std::time::{Duration, Instant};
+
+use crate::app::{App, ListRequests, Mode};
+use crate::check::CheckState;
+use crate::form::{ApiForm, FIELDS};
+use crate::history::History;
 
 #[macro_export]
 macro_rules! trace_dbg {
@@ -33,14 +38,409 @@ macro_rules! trace_dbg {
     };
 }
 
-pub fn ui(f: &mut Frame, app: &App) {
+const HOUR: Option<Duration> = Some(Duration::from_secs(60 * 60));
+const DAY: Option<Duration> = Some(Duration::from_secs(24 * 60 * 60));
+
+/// Short human readable duration, e.g. `45s`, `12m`, `3h`, `2d`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Rectangle of `width` x `height` centered in `area`, clamped to it.
+fn centered(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+fn render_form(f: &mut Frame, form: &ApiForm) {
+    let title = match &form.key {
+        Some(key) => format!(" Edit {key} "),
+        None => String::from(" Add API "),
+    };
+    let area = centered(90, FIELDS.len() as u16 + 6, f.size());
+
+    let mut lines: Vec<Line> = FIELDS
+        .iter()
+        .zip(&form.values)
+        .enumerate()
+        .map(|(index, (label, value))| {
+            let label = format!("{label:>16}: ");
+            if index == form.focus {
+                Line::from(vec![label.bold(), value.clone().reversed(), "_".into()])
+            } else {
+                Line::from(vec![label.into(), value.clone().into()])
+            }
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::from(form.error.clone().unwrap_or_default().red()));
+    lines.push(Line::from(vec![
+        " Next ".into(),
+        "<Tab> ".blue().bold(),
+        " Save ".into(),
+        "<Enter> ".blue().bold(),
+        " Cancel ".into(),
+        "<Esc> ".blue().bold(),
+        " Headers/Body: ".into(),
+        "key: value; key2: value2 (\\; for a literal ;)".italic(),
+    ]));
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(title.bold())
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        ),
+        area,
+    );
+}
+
+fn render_confirm_delete(f: &mut Frame, key: &str) {
+    let area = centered(50, 5, f.size());
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(vec![
+            Line::from(format!("Delete check {key}?")),
+            Line::from(vec![
+                "<y>".blue().bold(),
+                " yes  ".into(),
+                "<n>".blue().bold(),
+                " no".into(),
+            ]),
+        ])
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::default()
+                .title(" Delete API ".bold())
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        ),
+        area,
+    );
+}
+
+fn state_colour(state: CheckState) -> Color {
+    match state {
+        CheckState::Up => Color::Green,
+        CheckState::Down => Color::Red,
+        CheckState::Unknown => Color::Magenta,
+        CheckState::Degraded | CheckState::Pending => Color::Yellow,
+    }
+}
+
+/// Latency sparkline and up/down timeline of the recent results of `row`,
+/// one column per result.
+fn render_trend(f: &mut Frame, area: Rect, row: &ListRequests, history: Option<&History>) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)])
+        .split(area);
+    let samples = area.width.saturating_sub(2) as usize;
+
+    let mut recent: Vec<_> = history
+        .map(|h| h.iter().rev().take(samples).collect())
+        .unwrap_or_default();
+    recent.reverse();
+
+    let latencies: Vec<u64> = recent
+        .iter()
+        .map(|r| r.latency().map(|l| l.as_millis() as u64).unwrap_or(0))
+        .collect();
+    let max = latencies.iter().copied().max().unwrap_or(0);
+
+    let sparkline = Sparkline::default()
+        .block(
+            Block::default()
+                .title(format!(
+                    " {} latency, last {} results, max {max}ms ",
+                    row.data.name,
+                    latencies.len()
+                ))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        )
+        .data(&latencies)
+        .style(Style::default().fg(Color::Cyan));
+    f.render_widget(sparkline, chunks[0]);
+
+    let timeline: Vec<_> = recent
+        .iter()
+        .map(|r| "█".fg(state_colour(r.state)))
+        .collect();
+    f.render_widget(
+        Paragraph::new(Line::from(timeline)).block(
+            Block::default()
+                .title(" Up / down ")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        ),
+        chunks[1],
+    );
+}
+
+const KEYS: [(&str, &str); 21] = [
+    ("Up/Down, j/k", "select a check"),
+    ("PgUp/PgDn", "move the selection by a page"),
+    ("Home/End, g/G", "select the first / last check"),
+    ("Enter", "show details of the selected check"),
+    ("r", "run the selected check now"),
+    ("p", "pause / resume the selected check"),
+    ("m", "mute / unmute its notifications for an hour"),
+    ("a", "add a check"),
+    ("e", "edit the selected check"),
+    ("d", "delete the selected check"),
+    ("/", "search by name or URL"),
+    ("f", "next filter: all, failing, tags, groups"),
+    ("s", "next sort order: name, status, latency, next run"),
+    ("Esc", "reset search, filter and sort"),
+    ("?", "show this help"),
+    ("q", "quit"),
+    ("", ""),
+    ("In forms", ""),
+    ("Tab/Shift-Tab", "next / previous field"),
+    ("Enter", "save"),
+    ("Esc", "cancel"),
+];
+
+fn render_help(f: &mut Frame) {
+    let lines: Vec<Line> = KEYS
+        .iter()
+        .map(|(keys, action)| {
+            Line::from(vec![
+                format!(" {keys:>14}  ").blue().bold(),
+                action.to_string().into(),
+            ])
+        })
+        .collect();
+    let area = centered(70, lines.len() as u16 + 2, f.size());
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(" Key bindings ".bold())
+                .title(
+                    Title::from(" Close <any key> ")
+                        .alignment(Alignment::Center)
+                        .position(Position::Bottom),
+                )
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        ),
+        area,
+    );
+}
+
+/// Countdown to the next run, and whether the check is paused or muted.
+fn next_request(row: &ListRequests) -> String {
+    let next = if row.paused {
+        String::from("paused")
+    } else {
+        row.interval.to_string()
+    };
+    match row.muted_until {
+        Some(until) if row.is_muted() => {
+            let left = until.saturating_duration_since(Instant::now());
+            format!("{next} · muted {}", format_duration(left))
+        }
+        _ => next,
+    }
+}
+
+/// Results listed under "Recent history" in the detail view.
+const DETAIL_HISTORY: usize = 10;
+
+/// Whether a header carries credentials and must not be shown as is.
+fn is_secret_header(name: &str) -> bool {
+    let name = name.to_lowercase();
+    [
+        "authorization",
+        "cookie",
+        "token",
+        "secret",
+        "password",
+        "api-key",
+        "apikey",
+    ]
+    .iter()
+    .any(|secret| name.contains(secret))
+}
+
+fn header_line(name: &str, value: &str) -> Line<'static> {
+    let value = if is_secret_header(name) {
+        String::from("********")
+    } else {
+        value.to_string()
+    };
+    Line::from(vec![format!("  {name}: ").bold(), value.into()])
+}
+
+fn format_ms(duration: Option<Duration>) -> String {
+    duration
+        .map(|d| format!("{:.1}ms", d.as_secs_f64() * 1000.0))
+        .unwrap_or_else(|| String::from("-"))
+}
+
+fn render_detail(f: &mut Frame, app: &App, key: &str, scroll: u16) {
+    let Some(row) = app.row(key) else {
+        return;
+    };
+    let data = &row.data;
+    let result = &data.result;
+    let area = centered(f.size().width * 9 / 10, f.size().height * 9 / 10, f.size());
+    let section = |title: &str| Line::from(title.to_string().bold().underlined());
+
+    let mut lines = vec![
+        Line::from(vec!["URL: ".bold(), data.url.clone().into()]),
+        Line::from(vec!["Method: ".bold(), data.method.clone().into()]),
+    ];
+    let api = app.configs.requests.get(key);
+    if let Some(auth) = api.and_then(|api| api.request.auth.as_ref()) {
+        lines.push(Line::from(vec!["Auth: ".bold(), auth.to_string().into()]));
+    }
+    lines.push(Line::from(""));
+    lines.push(section("Request headers"));
+
+    let mut sent: Vec<(&String, &String)> = api
+        .and_then(|api| api.request.headers.as_ref())
+        .map(|headers| headers.iter().collect())
+        .unwrap_or_default();
+    sent.sort();
+    if sent.is_empty() {
+        lines.push(Line::from("  -"));
+    }
+    for (name, value) in sent {
+        lines.push(header_line(name, value));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(section("Last result"));
+    let mut status = format!("  {}", result.state);
+    if let Some(code) = result.http_status {
+        status = format!("{status}, HTTP {code}");
+    }
+    if let Some(kind) = result.error_kind {
+        status = format!("{status}, {kind}");
+    }
+    if let Some(time) = result.checked_at {
+        status = format!("{status}, at {}", time.format("%Y-%m-%d %H:%M:%S UTC"));
+    }
+    if result.attempts > 1 {
+        status = format!("{status}, {} attempts", result.attempts);
+    }
+    lines.push(Line::from(status));
+    if let Some(error) = &result.error_message {
+        lines.push(Line::from(format!("  {error}").red()));
+    }
+    for failure in &result.assertion_failures {
+        lines.push(Line::from(format!("  assertion: {failure}").red()));
+    }
+    if let Some(timings) = &result.timings {
+        lines.push(Line::from(format!(
+            "  dns {}  connect {}  tls {}  ttfb {}  total {}",
+            format_ms(timings.dns),
+            format_ms(timings.connect),
+            format_ms(timings.tls),
+            format_ms(Some(timings.ttfb)),
+            format_ms(Some(timings.total)),
+        )));
+    }
+
+    if let Some(response) = &result.response {
+        lines.push(Line::from(""));
+        lines.push(section("Response headers"));
+        for (name, value) in &response.headers {
+            lines.push(header_line(name, value));
+        }
+
+        lines.push(Line::from(""));
+        lines.push(section(&format!(
+            "Response body ({} of {} bytes)",
+            response.body.len(),
+            response.body_size
+        )));
+        for line in response.body.lines() {
+            lines.push(Line::from(format!("  {line}")));
+        }
+    }
+
+    lines.push(Line::from(""));
+    lines.push(section("Recent history"));
+    if let Some(history) = app.history.get(&row.id) {
+        for past in history.iter().rev().take(DETAIL_HISTORY) {
+            let time = past
+                .checked_at
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            let code = past
+                .http_status
+                .map(|code| code.to_string())
+                .unwrap_or_else(|| String::from("-"));
+            lines.push(Line::from(format!(
+                "  {time}  {:<8} {code:>4}  {:>9}",
+                past.state.to_string(),
+                format_ms(past.latency()),
+            )));
+        }
+    }
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .scroll((scroll, 0))
+            .block(
+                Block::default()
+                    .title(format!(" {} ({key}) ", data.name).bold())
+                    .title(
+                        Title::from(Line::from(vec![
+                            " Scroll ".into(),
+                            "<Up/Down/PgUp/PgDn>".blue().bold(),
+                            " Close ".into(),
+                            "<Esc> ".blue().bold(),
+                        ]))
+                        .alignment(Alignment::Center)
+                        .position(Position::Bottom),
+                    )
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded),
+            ),
+        area,
+    );
+}
+
+pub fn ui(f: &mut Frame, app: &mut App) {
     let title: Title<'static> = Title::from(" Health Crab TUI ".bold());
 
     let instructions = Title::from(Line::from(vec![
-        // " Add API ".into(),
-        // "<a>".blue().bold(),
-        // " Delete API ".into(),
-        // "<d>".blue().bold(),
+        " Select ".into(),
+        "<Up/Down>".blue().bold(),
+        " Details ".into(),
+        "<Enter>".blue().bold(),
+        " Run now ".into(),
+        "<r>".blue().bold(),
+        " Pause ".into(),
+        "<p>".blue().bold(),
+        " Search ".into(),
+        "</>".blue().bold(),
+        " Help ".into(),
+        "<?>".blue().bold(),
         " Quit ".into(),
         "<Q> ".blue().bold(),
     ]));
@@ -81,8 +481,33 @@ pub fn ui(f: &mut Frame, app: &App) {
             Constraint::Percentage((100 - 90) / 2),
         ])
         .split(chunks[2])[1];
+    let content = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(5), Constraint::Length(9)])
+        .split(content_center);
 
-    let header_info = Paragraph::new("API Monitoring")
+    let visible = app.visible_rows();
+    let header_text = match &app.notice {
+        Some(notice) => Line::from(notice.clone().red()),
+        None => {
+            let mut summary = format!(
+                "API Monitoring · {} of {} checks · filter {} · sort {}",
+                visible.len(),
+                app.apis_infos.len(),
+                app.view.filter,
+                app.view.sort
+            );
+            let searching = matches!(app.mode, Mode::Search);
+            if searching || !app.view.query.is_empty() {
+                summary = format!("{summary} · search: {}", app.view.query);
+            }
+            if searching {
+                summary.push('_');
+            }
+            Line::from(summary)
+        }
+    };
+    let header_info = Paragraph::new(header_text)
         .alignment(Alignment::Center)
         .block(
             Block::default()
@@ -96,34 +521,77 @@ pub fn ui(f: &mut Frame, app: &App) {
 
     let mut rows: Vec<Row> = Vec::new();
 
-    for api_info in &app.apis_infos {
+    for api_info in &visible {
         let data = &api_info.data;
 
-        let colour = match data.status.as_str() {
-            "OK" => Color::Green,
-            "ERROR" => Color::Red,
-            _ => Color::Yellow,
+        let result = &data.result;
+
+        let colour = state_colour(result.state);
+
+        let mut status = result.state.to_string();
+        match (result.http_status, result.error_kind) {
+            (Some(code), _) => status = format!("{status} {code}"),
+            (None, Some(kind)) => status = format!("{status} ({kind})"),
+            (None, None) => {}
+        }
+        if result.attempts > 1 {
+            status = format!("{status} x{}", result.attempts);
+        }
+
+        let latency = match result.latency() {
+            Some(latency) => format!("{}ms", latency.as_millis()),
+            None => String::from("-"),
+        };
+
+        let history = app.history.get(&api_info.id);
+        let uptime = match history {
+            Some(history) => [HOUR, DAY, None]
+                .map(|window| match history.uptime(window) {
+                    Some(uptime) => format!("{uptime:.1}"),
+                    None => String::from("-"),
+                })
+                .join(" / "),
+            None => String::from("-"),
         };
+        let latency_stats = match history.and_then(|h| h.mean_latency().zip(h.p95_latency())) {
+            Some((mean, p95)) => format!("{} / {}", mean.as_millis(), p95.as_millis()),
+            None => String::from("-"),
+        };
+        let changed = history.and_then(|h| {
+            h.time_since_state_change()
+                .map(|since| format!("{} ({}x)", format_duration(since), h.state_changes()))
+        });
 
         rows.push(Row::new(vec![
             Cell::from(Text::from(String::from(&data.name)).alignment(Alignment::Center)),
             Cell::from(Text::from(String::from(&data.method)).alignment(Alignment::Center)),
             Cell::from(Text::from(String::from(&data.url)).alignment(Alignment::Center)),
-            Cell::from(Text::from(String::from(&data.status)).alignment(Alignment::Center))
+            Cell::from(Text::from(status).alignment(Alignment::Center))
                 .style(Style::default().fg(colour)),
+            Cell::from(Text::from(latency).alignment(Alignment::Center)),
+            Cell::from(Text::from(latency_stats).alignment(Alignment::Center)),
+            Cell::from(Text::from(uptime).alignment(Alignment::Center)),
             Cell::from(
-                Text::from(String::from(&api_info.interval.to_string()))
+                Text::from(changed.unwrap_or_else(|| String::from("-")))
                     .alignment(Alignment::Center),
             ),
+            Cell::from(Text::from(next_request(api_info)).alignment(Alignment::Center)),
+            Cell::from(Text::from(result.error_message.clone().unwrap_or_default()))
+                .style(Style::default().fg(Color::Red)),
         ]))
     }
 
     let widths = [
         Constraint::Length(30),
         Constraint::Length(10),
-        Constraint::Length(100),
+        Constraint::Length(50),
         Constraint::Length(20),
+        Constraint::Length(10),
+        Constraint::Length(14),
+        Constraint::Length(22),
+        Constraint::Length(14),
         Constraint::Length(20),
+        Constraint::Min(20),
     ];
     let table: Table<'static> = Table::new(rows, widths)
         .column_spacing(1)
@@ -133,7 +601,12 @@ pub fn ui(f: &mut Frame, app: &App) {
                 Cell::from(Text::from("Method").alignment(Alignment::Center)),
                 Cell::from(Text::from("Url").alignment(Alignment::Center)),
                 Cell::from(Text::from("Status").alignment(Alignment::Center)),
+                Cell::from(Text::from("Latency").alignment(Alignment::Center)),
+                Cell::from(Text::from("Avg / p95 ms").alignment(Alignment::Center)),
+                Cell::from(Text::from("Uptime 1h / 24h / all").alignment(Alignment::Center)),
+                Cell::from(Text::from("Since change").alignment(Alignment::Center)),
                 Cell::from(Text::from("Next Request").alignment(Alignment::Center)),
+                Cell::from(Text::from("Details")),
             ])
             .style(Style::new().bold())
             .bottom_margin(1),
@@ -142,5 +615,23 @@ pub fn ui(f: &mut Frame, app: &App) {
         .highlight_style(Style::new().reversed())
         .highlight_symbol(">>");
 
-    f.render_widget(table, content_center);
+    let selected = app
+        .selected
+        .as_ref()
+        .and_then(|key| visible.iter().position(|row| row.key == *key));
+    app.table_state.select(selected);
+    f.render_stateful_widget(table, content[0], &mut app.table_state);
+
+    let trend_row = app.selected_row().or(app.visible_rows().first().copied());
+    if let Some(row) = trend_row {
+        render_trend(f, content[1], row, app.history.get(&row.id));
+    }
+
+    match &app.mode {
+        Mode::Normal | Mode::Search => {}
+        Mode::Form(form) => render_form(f, form),
+        Mode::ConfirmDelete(key) => render_confirm_delete(f, key),
+        Mode::Detail { key, scroll } => render_detail(f, app, key, *scroll),
+        Mode::Help => render_help(f),
+    }
 }
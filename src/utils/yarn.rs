@@ -1,56 +1,496 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
 
-use serde::{Deserialize, Serialize};
+use rand::Rng;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
 pub type ReqHash = HashMap<String, String>;
 
+/// HTTP verb of a request. Known verbs are matched case-insensitively,
+/// anything else is sent as-is as a custom method.
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(from = "String", into = "String")]
 pub enum ConfigMethod {
-    GET,
-    POST,
-    // DELETE,
-    // PUT,
-    // PATH,
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Head,
+    Options,
+    Custom(String),
 }
 
-impl ToString for ConfigMethod {
-    fn to_string(&self) -> String {
+impl ConfigMethod {
+    pub fn as_str(&self) -> &str {
         match self {
-            ConfigMethod::GET => String::from("GET"),
-            ConfigMethod::POST => String::from("POST"),
+            ConfigMethod::Get => "GET",
+            ConfigMethod::Post => "POST",
+            ConfigMethod::Put => "PUT",
+            ConfigMethod::Patch => "PATCH",
+            ConfigMethod::Delete => "DELETE",
+            ConfigMethod::Head => "HEAD",
+            ConfigMethod::Options => "OPTIONS",
+            ConfigMethod::Custom(method) => method.as_str(),
         }
     }
 }
 
+impl From<String> for ConfigMethod {
+    fn from(method: String) -> Self {
+        match method.to_ascii_uppercase().as_str() {
+            "GET" => ConfigMethod::Get,
+            "POST" => ConfigMethod::Post,
+            "PUT" => ConfigMethod::Put,
+            "PATCH" => ConfigMethod::Patch,
+            "DELETE" => ConfigMethod::Delete,
+            "HEAD" => ConfigMethod::Head,
+            "OPTIONS" => ConfigMethod::Options,
+            _ => ConfigMethod::Custom(method),
+        }
+    }
+}
+
+impl From<ConfigMethod> for String {
+    fn from(method: ConfigMethod) -> Self {
+        method.to_string()
+    }
+}
+
+impl fmt::Display for ConfigMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub struct Request {
     pub url: String,
+    /// Appended to the URL, encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<ReqHash>,
     pub headers: Option<ReqHash>,
     pub method: ConfigMethod,
     pub body: Option<ReqHash>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
+}
+
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyLocation {
+    #[default]
+    Header,
+    Query,
 }
 
+/// Credentials added to a request when it is sent.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Auth {
+    Basic {
+        username: String,
+        password: String,
+    },
+    Bearer {
+        token: String,
+    },
+    ApiKey {
+        /// Header or query parameter name.
+        name: String,
+        value: String,
+        #[serde(default, rename = "in")]
+        location: ApiKeyLocation,
+    },
+    /// Bearer token requested from `token_url` with the client credentials
+    /// grant, reused until it expires.
+    Oauth2ClientCredentials {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        #[serde(default)]
+        scopes: Vec<String>,
+    },
+}
+
+impl Auth {
+    /// Copy with `f` applied to every text setting, e.g. to expand it.
+    pub fn map_text(
+        &self,
+        mut f: impl FnMut(&str, &str) -> Result<String, String>,
+    ) -> Result<Auth, String> {
+        Ok(match self {
+            Auth::Basic { username, password } => Auth::Basic {
+                username: f("username", username)?,
+                password: f("password", password)?,
+            },
+            Auth::Bearer { token } => Auth::Bearer {
+                token: f("token", token)?,
+            },
+            Auth::ApiKey {
+                name,
+                value,
+                location,
+            } => Auth::ApiKey {
+                name: f("name", name)?,
+                value: f("value", value)?,
+                location: *location,
+            },
+            Auth::Oauth2ClientCredentials {
+                token_url,
+                client_id,
+                client_secret,
+                scopes,
+            } => Auth::Oauth2ClientCredentials {
+                token_url: f("token_url", token_url)?,
+                client_id: f("client_id", client_id)?,
+                client_secret: f("client_secret", client_secret)?,
+                scopes: scopes
+                    .iter()
+                    .map(|scope| f("scopes", scope))
+                    .collect::<Result<_, _>>()?,
+            },
+        })
+    }
+}
+
+impl fmt::Display for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::Basic { username, .. } => write!(f, "basic, user {username}"),
+            Auth::Bearer { .. } => write!(f, "bearer"),
+            Auth::ApiKey { name, location, .. } => match location {
+                ApiKeyLocation::Header => write!(f, "API key in header {name}"),
+                ApiKeyLocation::Query => write!(f, "API key in query parameter {name}"),
+            },
+            Auth::Oauth2ClientCredentials { token_url, .. } => {
+                write!(f, "OAuth2 client credentials from {token_url}")
+            }
+        }
+    }
+}
+
+/// A request run before a check, whose JSON response provides the
+/// variables of the next steps and of the check.
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub struct Depends {
+    /// Shown in errors, defaults to the key of a shared step or the position
+    /// in the chain.
+    #[serde(default, deserialize_with = "empty_if_null")]
     pub name: String,
+    /// Response fields used in the headers and body of the check. When any
+    /// is listed, templates may only use these.
+    #[serde(default)]
     pub header_fields: Vec<String>,
+    #[serde(default)]
     pub body_fields: Vec<String>,
     pub request: Request,
+    /// Variables set from the response, as `name: template`. When empty,
+    /// every field of the response is a variable.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub extract: ReqHash,
+    /// Seconds the response is reused by every check sending the same
+    /// request. Defaults to its `expires_in` field or the `exp` claim of a
+    /// JWT in it; `0` never reuses it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl: Option<u64>,
+}
+
+/// Read `key:` without a value as an empty string.
+fn empty_if_null<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// A step of a `depends_on` chain, written in place or as the name of a
+/// shared step under `steps`.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(untagged, try_from = "serde_yml::Value")]
+pub enum Step {
+    Shared(String),
+    Request(Box<Depends>),
+}
+
+impl TryFrom<serde_yml::Value> for Step {
+    type Error = String;
+
+    fn try_from(value: serde_yml::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_yml::Value::String(name) => Ok(Step::Shared(name)),
+            value => serde_yml::from_value(value)
+                .map(|depends| Step::Request(Box::new(depends)))
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// A single step, or a chain of steps run in order.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(untagged, try_from = "serde_yml::Value")]
+pub enum DependsOn {
+    Chain(Vec<Step>),
+    One(Step),
+}
+
+impl TryFrom<serde_yml::Value> for DependsOn {
+    type Error = String;
+
+    fn try_from(value: serde_yml::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_yml::Value::Sequence(steps) => steps
+                .into_iter()
+                .enumerate()
+                .map(|(index, step)| Step::try_from(step).map_err(|e| format!("step {index}: {e}")))
+                .collect::<Result<_, _>>()
+                .map(DependsOn::Chain),
+            value => Step::try_from(value).map(DependsOn::One),
+        }
+    }
+}
+
+impl DependsOn {
+    pub fn steps(&self) -> &[Step] {
+        match self {
+            DependsOn::Chain(steps) => steps,
+            DependsOn::One(step) => std::slice::from_ref(step),
+        }
+    }
+
+    /// Whether `name` is one of the shared steps used.
+    pub fn uses(&self, name: &str) -> bool {
+        self.steps()
+            .iter()
+            .any(|step| matches!(step, Step::Shared(shared) if shared == name))
+    }
+
+    /// Copy with every shared step replaced by its definition. Steps
+    /// without a name are named after their key or position.
+    pub fn resolve(&self, shared: &HashMap<String, Depends>) -> Result<DependsOn, String> {
+        let steps = self
+            .steps()
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                let (mut depends, default_name) = match step {
+                    Step::Shared(name) => (
+                        shared
+                            .get(name)
+                            .cloned()
+                            .ok_or_else(|| format!("unknown step {name}"))?,
+                        name.to_string(),
+                    ),
+                    Step::Request(depends) => (*depends.clone(), format!("step {}", index + 1)),
+                };
+                if depends.name.is_empty() {
+                    depends.name = default_name;
+                }
+                Ok(Step::Request(Box::new(depends)))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(DependsOn::Chain(steps))
+    }
+}
+
+/// Extra checks run against a response once its status matched.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Assertion {
+    JsonEquals {
+        path: String,
+        value: Value,
+    },
+    JsonNotEquals {
+        path: String,
+        value: Value,
+    },
+    BodyMatches {
+        pattern: String,
+    },
+    BodyContains {
+        text: String,
+    },
+    HeaderExists {
+        name: String,
+    },
+    HeaderEquals {
+        name: String,
+        value: String,
+    },
+    BodySize {
+        min: Option<usize>,
+        max: Option<usize>,
+    },
+}
+
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BackoffStrategy {
+    #[default]
+    Fixed,
+    Exponential,
+}
+
+/// Wait between two attempts of the same check.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct RetryBackoff {
+    #[serde(default)]
+    pub strategy: BackoffStrategy,
+    #[serde(default = "default_retry_delay_ms")]
+    pub delay_ms: u64,
+    pub max_delay_ms: Option<u64>,
+    #[serde(default)]
+    pub jitter: bool,
+}
+
+fn default_retry_delay_ms() -> u64 {
+    1000
+}
+
+pub(crate) fn default_timeout() -> u64 {
+    30
+}
+
+fn default_history_size() -> usize {
+    1000
+}
+
+fn default_load_hours() -> u64 {
+    24
+}
+
+fn default_metrics_path() -> String {
+    String::from("/metrics")
+}
+
+fn default_status_page_title() -> String {
+    String::from("Health Crab")
+}
+
+fn default_status_page_refresh() -> u64 {
+    10
+}
+
+impl Default for RetryBackoff {
+    fn default() -> Self {
+        RetryBackoff {
+            strategy: BackoffStrategy::default(),
+            delay_ms: default_retry_delay_ms(),
+            max_delay_ms: None,
+            jitter: false,
+        }
+    }
+}
+
+impl RetryBackoff {
+    /// Delay to wait after the given (1-based) failed attempt.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let mut delay_ms = match self.strategy {
+            BackoffStrategy::Fixed => self.delay_ms,
+            BackoffStrategy::Exponential => self
+                .delay_ms
+                .saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1))),
+        };
+
+        if let Some(max) = self.max_delay_ms {
+            delay_ms = delay_ms.min(max);
+        }
+
+        if self.jitter && delay_ms > 1 {
+            delay_ms = delay_ms / 2 + rand::thread_rng().gen_range(0..=delay_ms / 2);
+        }
+
+        Duration::from_millis(delay_ms)
+    }
 }
 
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub struct Api {
     pub name: String,
-    pub depends_on: Option<Depends>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Section of the status page the check is listed under.
+    pub group: Option<String>,
+    pub depends_on: Option<DependsOn>,
     pub request: Request,
     pub expected_status: u16,
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+    pub warn_latency_ms: Option<u64>,
+    pub max_latency_ms: Option<u64>,
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    #[serde(default)]
+    pub retries: u32,
+    #[serde(default)]
+    pub retry_backoff: RetryBackoff,
     pub interval: u64,
     pub system_notify: bool,
+    /// Kept so older configs still load; it has no effect.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub notify_type: String,
     // pub one_time_notify: bool,
 }
 
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    #[default]
+    Sqlite,
+    Jsonl,
+}
+
+/// Where check results are persisted between runs.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct StorageConfig {
+    #[serde(default)]
+    pub backend: StorageBackend,
+    pub path: String,
+    /// Results newer than this are loaded back into the history at startup.
+    #[serde(default = "default_load_hours")]
+    pub load_hours: u64,
+}
+
+/// Embedded Prometheus endpoint.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct MetricsConfig {
+    /// Address to listen on, e.g. `127.0.0.1:9185`.
+    pub listen: String,
+    #[serde(default = "default_metrics_path")]
+    pub path: String,
+}
+
+/// Read-only HTML status page.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct StatusPageConfig {
+    pub listen: String,
+    #[serde(default = "default_status_page_title")]
+    pub title: String,
+    /// Seconds between automatic reloads of the page.
+    #[serde(default = "default_status_page_refresh")]
+    pub refresh: u64,
+}
+
+/// Local REST API to manage checks at runtime.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct ControlConfig {
+    pub listen: String,
+    /// When set, requests must send `Authorization: Bearer <token>`.
+    pub token: Option<String>,
+}
+
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub struct ApisConfig {
     pub requests: HashMap<String, Api>,
+    /// `depends_on` steps shared by several checks, referenced by key.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub steps: HashMap<String, Depends>,
+    /// Number of past results kept per check.
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+    pub storage: Option<StorageConfig>,
+    pub metrics: Option<MetricsConfig>,
+    pub status_page: Option<StatusPageConfig>,
+    pub control: Option<ControlConfig>,
 }
@@ -0,0 +1,569 @@
+use regex::Regex;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Method;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+
+use crate::interpolate::{expand_api, expand_depends, Secrets};
+use crate::template::{variable_path, Template};
+use crate::utils::yarn::{
+    Api, ApiKeyLocation, ApisConfig, Assertion, Auth, Depends, DependsOn, ReqHash, Request, Step,
+};
+
+/// One problem found in the config file.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// Dotted path of the value, e.g. `requests.users.request.url`.
+    pub path: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.path.is_empty()) {
+            (Some(line), false) => write!(f, "line {line}: {}: {}", self.path, self.message),
+            (Some(line), true) => write!(f, "line {line}: {}", self.message),
+            (None, false) => write!(f, "{}: {}", self.path, self.message),
+            (None, true) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+enum Frame {
+    Map { key: Option<String> },
+    Seq { index: usize },
+}
+
+/// Records the line of every key and sequence item of a YAML document.
+#[derive(Default)]
+struct LineRecorder {
+    stack: Vec<Frame>,
+    lines: HashMap<String, usize>,
+}
+
+impl LineRecorder {
+    fn path(&self) -> String {
+        let mut path = String::new();
+        for frame in &self.stack {
+            match frame {
+                Frame::Map { key: Some(key) } => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(key);
+                }
+                Frame::Map { key: None } => {}
+                Frame::Seq { index } => path.push_str(&format!("[{index}]")),
+            }
+        }
+        path
+    }
+
+    /// Called before a value (scalar, mapping or sequence) starts.
+    fn enter_value(&mut self, line: usize) {
+        if let Some(Frame::Seq { .. }) = self.stack.last() {
+            let path = self.path();
+            self.lines.entry(path).or_insert(line);
+        }
+    }
+
+    /// Called once a value is complete.
+    fn leave_value(&mut self) {
+        match self.stack.last_mut() {
+            Some(Frame::Map { key }) => *key = None,
+            Some(Frame::Seq { index }) => *index += 1,
+            None => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for LineRecorder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        let line = mark.line();
+        match event {
+            Event::Scalar(value, ..) => {
+                if let Some(Frame::Map { key: key @ None }) = self.stack.last_mut() {
+                    *key = Some(value);
+                    let path = self.path();
+                    self.lines.entry(path).or_insert(line);
+                } else {
+                    self.enter_value(line);
+                    self.leave_value();
+                }
+            }
+            Event::Alias(_) => {
+                self.enter_value(line);
+                self.leave_value();
+            }
+            Event::MappingStart(_) => {
+                self.enter_value(line);
+                self.stack.push(Frame::Map { key: None });
+            }
+            Event::SequenceStart(_) => {
+                self.enter_value(line);
+                self.stack.push(Frame::Seq { index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+                self.leave_value();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Line of every key of `content`. Paths that cannot be located, e.g. in a
+/// file that is not valid YAML, are simply missing.
+fn yaml_lines(content: &str) -> HashMap<String, usize> {
+    let mut recorder = LineRecorder::default();
+    let _ = Parser::new(content.chars()).load(&mut recorder, false);
+    recorder.lines
+}
+
+/// Line of `path`, or of its closest parent found in the file.
+fn line_of(lines: &HashMap<String, usize>, path: &str) -> Option<usize> {
+    let mut path = path;
+    loop {
+        if let Some(line) = lines.get(path) {
+            return Some(*line);
+        }
+        path = &path[..path.rfind(['.', '['])?];
+    }
+}
+
+/// Variables a request template may use.
+struct Scope<'a> {
+    /// Variables set by earlier steps.
+    known: Vec<&'a String>,
+    /// An earlier step made every field of its response a variable.
+    any: bool,
+    /// Shown when no variable is known yet.
+    reason: &'static str,
+}
+
+impl<'a> Scope<'a> {
+    fn new(reason: &'static str) -> Scope<'a> {
+        Scope {
+            known: Vec::new(),
+            any: false,
+            reason,
+        }
+    }
+
+    fn any() -> Scope<'a> {
+        Scope {
+            any: true,
+            ..Scope::new("")
+        }
+    }
+
+    /// Add the variables set by `step`.
+    fn add(&mut self, step: &'a Depends) {
+        if !step.extract.is_empty() {
+            self.known.extend(step.extract.keys());
+        } else if step.header_fields.is_empty() && step.body_fields.is_empty() {
+            self.any = true;
+        } else {
+            self.known
+                .extend(step.header_fields.iter().chain(&step.body_fields));
+        }
+    }
+
+    fn check(&self, path: &str) -> Result<(), String> {
+        let covered = self.any
+            || self.known.iter().any(|known| {
+                let known = variable_path(known);
+                path == known || path.starts_with(&format!("{known}."))
+            });
+
+        match (covered, self.known.is_empty()) {
+            (true, _) => Ok(()),
+            (false, true) => Err(format!("unknown variable {path}, {}", self.reason)),
+            (false, false) => Err(format!(
+                "unknown variable {path}, not provided by depends_on"
+            )),
+        }
+    }
+}
+
+/// Problems of the template in `text`, reported at `path`.
+fn check_template(problems: &mut Vec<(String, String)>, path: &str, text: &str, scope: &Scope) {
+    match Template::parse(text) {
+        Ok(template) => {
+            for variable in template.variables() {
+                if let Err(e) = scope.check(variable) {
+                    problems.push((path.to_string(), e));
+                }
+            }
+        }
+        Err(e) => problems.push((path.to_string(), format!("invalid template: {e}"))),
+    }
+}
+
+fn check_url(problems: &mut Vec<(String, String)>, path: String, url: &str) {
+    // Only the start of a templated URL is known before it is rendered.
+    let (url, templated) = match url.split_once("{{") {
+        Some((start, _)) => (start, true),
+        None => (url, false),
+    };
+    match reqwest::Url::parse(url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        Ok(url) => problems.push((
+            path,
+            format!(
+                "unsupported scheme {}, expected http or https",
+                url.scheme()
+            ),
+        )),
+        Err(_) if templated => {}
+        Err(e) => problems.push((path, format!("invalid URL: {e}"))),
+    }
+}
+
+fn check_request(
+    problems: &mut Vec<(String, String)>,
+    path: &str,
+    request: &Request,
+    scope: &Scope,
+) {
+    let url_path = format!("{path}.url");
+    check_template(problems, &url_path, &request.url, scope);
+    check_url(problems, url_path, &request.url);
+
+    if Method::from_bytes(request.method.as_str().as_bytes()).is_err() {
+        problems.push((
+            format!("{path}.method"),
+            format!("invalid method {}", request.method),
+        ));
+    }
+
+    for (name, value) in sorted(&request.query) {
+        let query_path = format!("{path}.query.{name}");
+        check_template(problems, &query_path, name, scope);
+        check_template(problems, &query_path, value, scope);
+    }
+
+    for (name, value) in sorted(&request.headers) {
+        let header_path = format!("{path}.headers.{name}");
+        if !name.contains("{{") && HeaderName::from_bytes(name.as_bytes()).is_err() {
+            problems.push((header_path.clone(), format!("invalid header name {name:?}")));
+        }
+        if HeaderValue::from_str(value).is_err() {
+            problems.push((header_path.clone(), String::from("invalid header value")));
+        }
+        check_template(problems, &header_path, name, scope);
+        check_template(problems, &header_path, value, scope);
+    }
+
+    for (name, value) in sorted(&request.body) {
+        let body_path = format!("{path}.body.{name}");
+        check_template(problems, &body_path, name, scope);
+        check_template(problems, &body_path, value, scope);
+    }
+
+    if let Some(auth) = &request.auth {
+        check_auth(problems, &format!("{path}.auth"), auth, scope);
+    }
+}
+
+fn check_auth(problems: &mut Vec<(String, String)>, path: &str, auth: &Auth, scope: &Scope) {
+    let _ = auth.map_text(|field, text| {
+        check_template(problems, &format!("{path}.{field}"), text, scope);
+        Ok(String::new())
+    });
+
+    match auth {
+        Auth::ApiKey {
+            name,
+            location: ApiKeyLocation::Header,
+            ..
+        } if !name.contains("{{") && HeaderName::from_bytes(name.as_bytes()).is_err() => {
+            problems.push((
+                format!("{path}.name"),
+                format!("invalid header name {name:?}"),
+            ));
+        }
+        Auth::Oauth2ClientCredentials { token_url, .. } => {
+            check_url(problems, format!("{path}.token_url"), token_url);
+        }
+        _ => {}
+    }
+}
+
+fn sorted(pairs: &Option<ReqHash>) -> BTreeMap<&String, &String> {
+    pairs.iter().flatten().collect()
+}
+
+fn sorted_map(pairs: &ReqHash) -> BTreeMap<&String, &String> {
+    pairs.iter().collect()
+}
+
+/// Problems of a single check, with paths under `requests.<key>`. The
+/// check is validated with its `${...}` references expanded, against the
+/// shared steps in `shared`.
+pub fn check_api(key: &str, api: &Api, shared: &HashMap<String, Depends>) -> Vec<(String, String)> {
+    let path = format!("requests.{key}");
+    let mut secrets = Secrets::default();
+    let api = match expand_api(api, &mut secrets) {
+        Ok(api) => api,
+        Err(e) => return vec![(path, e)],
+    };
+
+    check_expanded(&path, &api, shared)
+        .into_iter()
+        .map(|(path, message)| (path, secrets.mask(&message)))
+        .collect()
+}
+
+/// Message when a check of `requests` other than `key`, the one being
+/// edited, already has the name of `api`.
+pub fn duplicate_name(
+    key: Option<&str>,
+    api: &Api,
+    requests: &HashMap<String, Api>,
+) -> Option<String> {
+    requests
+        .iter()
+        .filter(|(other, other_api)| Some(other.as_str()) != key && other_api.name == api.name)
+        .map(|(other, _)| other)
+        .min()
+        .map(|other| format!("duplicate name {:?}, also used by {other}", api.name))
+}
+
+/// Steps of the `depends_on` of `api` with their paths.
+fn steps_of<'a>(path: &str, api: &'a Api) -> Vec<(String, &'a Step)> {
+    match &api.depends_on {
+        Some(DependsOn::One(step)) => vec![(format!("{path}.depends_on"), step)],
+        Some(DependsOn::Chain(steps)) => steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| (format!("{path}.depends_on[{index}]"), step))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Problems of the request and extracted variables of a step.
+fn check_step(problems: &mut Vec<(String, String)>, path: &str, step: &Depends, scope: &Scope) {
+    check_request(problems, &format!("{path}.request"), &step.request, scope);
+    for (name, template) in sorted_map(&step.extract) {
+        // Extracted values also see the response of the step itself.
+        check_template(
+            problems,
+            &format!("{path}.extract.{name}"),
+            template,
+            &Scope::any(),
+        );
+    }
+}
+
+fn check_expanded(
+    path: &str,
+    api: &Api,
+    shared: &HashMap<String, Depends>,
+) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+
+    if api.name.trim().is_empty() {
+        problems.push((format!("{path}.name"), String::from("name is empty")));
+    }
+    if api.interval == 0 {
+        problems.push((format!("{path}.interval"), String::from("must be above 0")));
+    }
+    if api.timeout == 0 {
+        problems.push((format!("{path}.timeout"), String::from("must be above 0")));
+    }
+    if let (Some(warn), Some(max)) = (api.warn_latency_ms, api.max_latency_ms) {
+        if warn > max {
+            problems.push((
+                format!("{path}.warn_latency_ms"),
+                format!("{warn} is above max_latency_ms {max}"),
+            ));
+        }
+    }
+
+    let mut scope = match &api.depends_on {
+        Some(_) => Scope::new("the first step of depends_on can only use built-ins"),
+        None => Scope::new("the check has no depends_on"),
+    };
+    for (step_path, step) in steps_of(path, api) {
+        let depends = match step {
+            Step::Request(depends) => {
+                check_step(&mut problems, &step_path, depends, &scope);
+                depends
+            }
+            Step::Shared(name) => match shared.get(name) {
+                Some(depends) => depends,
+                None => {
+                    problems.push((step_path, format!("unknown step {name}, not under steps")));
+                    // Its variables are unknown, do not report them as well.
+                    scope.any = true;
+                    continue;
+                }
+            },
+        };
+        scope.add(depends);
+    }
+    check_request(
+        &mut problems,
+        &format!("{path}.request"),
+        &api.request,
+        &scope,
+    );
+
+    for (index, assertion) in api.assertions.iter().enumerate() {
+        if let Assertion::BodyMatches { pattern } = assertion {
+            if let Err(e) = Regex::new(pattern) {
+                problems.push((
+                    format!("{path}.assertions[{index}].pattern"),
+                    // The last line holds the reason, the others draw the pattern.
+                    format!(
+                        "invalid regex: {}",
+                        e.to_string()
+                            .lines()
+                            .last()
+                            .unwrap_or_default()
+                            .trim_start_matches("error: ")
+                    ),
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Problems of the metrics endpoint path, which is served next to the
+/// status page (`/`) and the control API (`/checks...`) when they share its
+/// `listen` address.
+fn check_metrics_path(problems: &mut Vec<(String, String)>, configs: &ApisConfig) {
+    let Some(metrics) = &configs.metrics else {
+        return;
+    };
+    let path = metrics.path.as_str();
+    let shares = |listen: Option<&String>| listen == Some(&metrics.listen);
+
+    let problem = if !path.starts_with('/') {
+        Some(String::from("must start with `/`"))
+    } else if path.contains([':', '*']) {
+        Some(String::from("must not contain `:` or `*`"))
+    } else if path == "/" && shares(configs.status_page.as_ref().map(|page| &page.listen)) {
+        Some(String::from(
+            "`/` is the status page on the same listen address",
+        ))
+    } else if (path == "/checks" || path.starts_with("/checks/"))
+        && shares(configs.control.as_ref().map(|control| &control.listen))
+    {
+        Some(String::from(
+            "`/checks` is the control API on the same listen address",
+        ))
+    } else {
+        None
+    };
+
+    if let Some(message) = problem {
+        problems.push((String::from("metrics.path"), message));
+    }
+}
+
+/// Every problem of `configs`, without line numbers.
+pub fn check_config(configs: &ApisConfig) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    check_metrics_path(&mut problems, configs);
+    let requests: BTreeMap<&String, &Api> = configs.requests.iter().collect();
+    let mut names: HashMap<&str, &str> = HashMap::new();
+
+    let steps: BTreeMap<&String, &Depends> = configs.steps.iter().collect();
+    for (name, step) in steps {
+        let path = format!("steps.{name}");
+        let mut secrets = Secrets::default();
+        match expand_depends(step, &mut secrets) {
+            // Variables depend on the chain the step is used in.
+            Ok(step) => check_step(&mut problems, &path, &step, &Scope::any()),
+            Err(e) => problems.push((path, e)),
+        }
+    }
+
+    for (key, api) in requests {
+        problems.extend(check_api(key, api, &configs.steps));
+
+        match names.get(api.name.as_str()) {
+            Some(other) => problems.push((
+                format!("requests.{key}.name"),
+                format!("duplicate name {:?}, also used by {other}", api.name),
+            )),
+            None => {
+                names.insert(&api.name, key);
+            }
+        }
+    }
+
+    problems
+}
+
+/// Parse and validate the config file at `path`.
+pub fn load(path: &str) -> Result<ApisConfig, Vec<Problem>> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        vec![Problem {
+            path: String::new(),
+            line: None,
+            message: format!("cannot read {path}: {e}"),
+        }]
+    })?;
+
+    let configs = serde_yml::from_str::<ApisConfig>(&content).map_err(|e| {
+        // The location is reported separately.
+        let mut message = e.to_string();
+        if let Some(at) = message.rfind(" at line ") {
+            message.truncate(at);
+        }
+        vec![Problem {
+            path: String::new(),
+            line: e.location().map(|l| l.line()),
+            message,
+        }]
+    })?;
+
+    let lines = yaml_lines(&content);
+    let problems: Vec<Problem> = check_config(&configs)
+        .into_iter()
+        .map(|(path, message)| Problem {
+            line: line_of(&lines, &path),
+            path,
+            message,
+        })
+        .collect();
+
+    if problems.is_empty() {
+        Ok(configs)
+    } else {
+        Err(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_of_falls_back_to_the_closest_parent() {
+        let lines = yaml_lines(
+            "requests:
+  api:
+    name: api
+    assertions:
+      - type: body_contains
+        text: ok
+",
+        );
+
+        assert_eq!(line_of(&lines, "requests.api.name"), Some(3));
+        assert_eq!(line_of(&lines, "requests.api.assertions[0].text"), Some(6));
+        assert_eq!(line_of(&lines, "requests.api.request.url"), Some(2));
+        assert_eq!(line_of(&lines, "steps.login"), None);
+    }
+}
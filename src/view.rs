@@ -0,0 +1,162 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::app::ListRequests;
+use crate::check::CheckState;
+use crate::utils::yarn::Api;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    All,
+    Failing,
+    Tag(String),
+    Group(String),
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::All => write!(f, "all"),
+            Filter::Failing => write!(f, "failing"),
+            Filter::Tag(tag) => write!(f, "tag {tag}"),
+            Filter::Group(group) => write!(f, "group {group}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortBy {
+    #[default]
+    Name,
+    Status,
+    Latency,
+    NextRun,
+}
+
+impl SortBy {
+    fn next(self) -> SortBy {
+        match self {
+            SortBy::Name => SortBy::Status,
+            SortBy::Status => SortBy::Latency,
+            SortBy::Latency => SortBy::NextRun,
+            SortBy::NextRun => SortBy::Name,
+        }
+    }
+}
+
+impl fmt::Display for SortBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SortBy::Name => "name",
+            SortBy::Status => "status",
+            SortBy::Latency => "latency",
+            SortBy::NextRun => "next run",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Failing checks first.
+fn state_rank(state: CheckState) -> u8 {
+    match state {
+        CheckState::Down => 0,
+        CheckState::Unknown => 1,
+        CheckState::Degraded => 2,
+        CheckState::Pending => 3,
+        CheckState::Up => 4,
+    }
+}
+
+/// Which rows of the TUI table are shown, and in which order.
+#[derive(Debug, Clone)]
+pub struct View {
+    /// Case-insensitive text matched against the name and URL.
+    pub query: String,
+    pub filter: Filter,
+    pub sort: SortBy,
+}
+
+impl Default for View {
+    fn default() -> View {
+        View {
+            query: String::new(),
+            filter: Filter::All,
+            sort: SortBy::default(),
+        }
+    }
+}
+
+impl View {
+    fn matches(&self, row: &ListRequests, api: Option<&Api>) -> bool {
+        let query = self.query.to_lowercase();
+        if !query.is_empty()
+            && !row.data.name.to_lowercase().contains(&query)
+            && !row.data.url.to_lowercase().contains(&query)
+        {
+            return false;
+        }
+
+        match &self.filter {
+            Filter::All => true,
+            Filter::Failing => row.data.result.is_failure(),
+            Filter::Tag(tag) => api.is_some_and(|api| api.tags.contains(tag)),
+            Filter::Group(group) => api.is_some_and(|api| api.group.as_ref() == Some(group)),
+        }
+    }
+
+    fn compare(&self, a: &ListRequests, b: &ListRequests) -> Ordering {
+        let order = match self.sort {
+            SortBy::Name => Ordering::Equal,
+            SortBy::Status => state_rank(a.data.result.state).cmp(&state_rank(b.data.result.state)),
+            // Slowest first, checks without a result last.
+            SortBy::Latency => b.data.result.latency().cmp(&a.data.result.latency()),
+            SortBy::NextRun => (a.paused, a.interval).cmp(&(b.paused, b.interval)),
+        };
+        // Ties are broken by name and key so rows do not jump around.
+        order
+            .then_with(|| a.data.name.cmp(&b.data.name))
+            .then_with(|| a.key.cmp(&b.key))
+    }
+
+    /// Rows to show, filtered and sorted.
+    pub fn apply<'a>(
+        &self,
+        rows: &'a [ListRequests],
+        configs: &HashMap<String, Api>,
+    ) -> Vec<&'a ListRequests> {
+        let mut visible: Vec<&ListRequests> = rows
+            .iter()
+            .filter(|row| self.matches(row, configs.get(&row.key)))
+            .collect();
+        visible.sort_by(|a, b| self.compare(a, b));
+        visible
+    }
+
+    pub fn next_sort(&mut self) {
+        self.sort = self.sort.next();
+    }
+
+    /// Cycle through all, failing, then every tag and group in use.
+    pub fn next_filter(&mut self, configs: &HashMap<String, Api>) {
+        let tags: BTreeSet<&String> = configs.values().flat_map(|api| &api.tags).collect();
+        let groups: BTreeSet<&String> = configs
+            .values()
+            .filter_map(|api| api.group.as_ref())
+            .collect();
+
+        let mut filters = vec![Filter::All, Filter::Failing];
+        filters.extend(tags.into_iter().map(|tag| Filter::Tag(tag.to_string())));
+        filters.extend(
+            groups
+                .into_iter()
+                .map(|group| Filter::Group(group.to_string())),
+        );
+
+        let current = filters.iter().position(|f| *f == self.filter);
+        self.filter = match current {
+            Some(index) => filters[(index + 1) % filters.len()].clone(),
+            None => Filter::All,
+        };
+    }
+}